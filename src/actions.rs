@@ -0,0 +1,194 @@
+//! What gets passed between the parts of the UI. The key handler & the fetcher send `Action`s to
+//! the reducer, which is the only thing that changes `UiState`, and the reducer hands the fetcher
+//! `Command`s for anything that has to go to Home Assistant.
+use std::collections::HashMap;
+
+use crossterm::event::KeyEvent;
+
+use haoscli::error::{Error, Result};
+use haoscli::types::{Addon, AddonAction, Backup, Event, Service, State, StatisticsPeriod};
+
+use crate::ui_types::{BackupAction, EnergySummary};
+
+#[derive(Debug)]
+pub enum Action {
+    /// A key press, what it means depends on the pane that's showing.
+    Key(KeyEvent),
+    /// The terminal changed size, there's nothing to do but redraw.
+    Resize,
+    /// Time to poll again, sent every `poll_rate`.
+    Tick,
+    /// Something the fetcher got back.
+    Response(Response),
+    /// A signal asked us to stop, IE: SIGTERM from a service manager.
+    Quit(Quit),
+}
+
+/// Why the UI closed, which decides what haoscli exits with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quit {
+    /// q was pressed.
+    Key,
+    /// Ctrl+c, or SIGINT from outside.
+    Interrupt,
+    /// SIGTERM.
+    Terminate,
+}
+
+impl Quit {
+    /// 0 for q, & 128 plus the signal's number for the rest like a shell would report.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Quit::Key => 0,
+            Quit::Interrupt => 130,
+            Quit::Terminate => 143,
+        }
+    }
+}
+
+/// Everything the fetcher can be asked to do.
+#[derive(Debug)]
+pub enum Command {
+    FetchEvents,
+    FetchServices,
+    FetchStates,
+    FetchAddons,
+    FetchAddonLogs(String),
+    FetchBackups,
+    /// The period to bucket by & entity ids to friendly names for labelling the devices.
+    FetchEnergy(StatisticsPeriod, HashMap<String, String>),
+    /// A change the user asked for. The id ties the answer back to the request the UI is showing.
+    Send(RequestId, Change),
+    /// Changes that were queued while offline, sent one after the other so they happen in the order
+    /// they were made.
+    Replay(Vec<(RequestId, Change)>),
+    /// Closes the websocket so whatever needs it next opens a fresh one, IE: after Home Assistant
+    /// restarted underneath it.
+    ResetWebSocket,
+}
+
+/// Handed out by `UiState::send`, one for each change.
+pub type RequestId = u64;
+
+/// The changes that can be made from the UI.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// Start, stop etc. the add-on with this slug.
+    Addon(String, AddonAction),
+    Backup(BackupAction),
+    SetState(State, HashMap<String, String>),
+    CallService {
+        domain: String,
+        service: String,
+        entity_id: String,
+    },
+}
+
+/// What came back for a `Command`.
+#[derive(Debug)]
+pub enum Response {
+    Events(Result<Vec<Event>>),
+    Services(Result<Vec<Service>>),
+    States(Result<Vec<State>>),
+    Addons(Result<Vec<Addon>>),
+    /// The slug the logs are for, already stripped of colour codes.
+    AddonLogs(String, Result<String>),
+    Backups(Result<Vec<Backup>>),
+    Energy(StatisticsPeriod, Result<EnergySummary>),
+    Sent(RequestId, Result<Applied>),
+}
+
+/// What Home Assistant said a change did.
+#[derive(Debug)]
+pub struct Applied {
+    /// Described from what it sent back, IE: "light.desk is now off".
+    pub summary: String,
+    /// The states it says the change left behind, shown before the next fetch confirms them.
+    pub changed: Vec<State>,
+}
+
+/// The things that get polled. Only one fetch of each is let out at a time so a slow answer doesn't
+/// pile more requests up behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Events,
+    Services,
+    States,
+    Addons,
+    AddonLogs,
+    Backups,
+    Energy,
+}
+
+impl Command {
+    /// What this polls, `None` for the ones that don't.
+    pub fn resource(&self) -> Option<Resource> {
+        match self {
+            Command::FetchEvents => Some(Resource::Events),
+            Command::FetchServices => Some(Resource::Services),
+            Command::FetchStates => Some(Resource::States),
+            Command::FetchAddons => Some(Resource::Addons),
+            Command::FetchAddonLogs(_) => Some(Resource::AddonLogs),
+            Command::FetchBackups => Some(Resource::Backups),
+            Command::FetchEnergy(..) => Some(Resource::Energy),
+            Command::Send(..) | Command::Replay(_) | Command::ResetWebSocket => None,
+        }
+    }
+}
+
+impl Response {
+    /// Which poll this answers, if it was one.
+    pub fn resource(&self) -> Option<Resource> {
+        match self {
+            Response::Events(_) => Some(Resource::Events),
+            Response::Services(_) => Some(Resource::Services),
+            Response::States(_) => Some(Resource::States),
+            Response::Addons(_) => Some(Resource::Addons),
+            Response::AddonLogs(..) => Some(Resource::AddonLogs),
+            Response::Backups(_) => Some(Resource::Backups),
+            Response::Energy(..) => Some(Resource::Energy),
+            Response::Sent(..) => None,
+        }
+    }
+
+    /// What went wrong, if anything did.
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            Response::Events(Err(e))
+            | Response::Services(Err(e))
+            | Response::States(Err(e))
+            | Response::Addons(Err(e))
+            | Response::AddonLogs(_, Err(e))
+            | Response::Backups(Err(e))
+            | Response::Energy(_, Err(e))
+            | Response::Sent(_, Err(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Change {
+    /// What's being done, for the spinner while it's on its way.
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Addon(slug, action) => format!("{:?} {}", action, slug),
+            Change::Backup(BackupAction::Create(name)) if name.is_empty() => String::from("Creating a backup"),
+            Change::Backup(BackupAction::Create(name)) => format!("Creating backup {}", name),
+            Change::Backup(BackupAction::Delete(slug)) => format!("Deleting backup {}", slug),
+            Change::Backup(BackupAction::Download(slug)) => format!("Downloading backup {}", slug),
+            Change::SetState(state, _) => format!("Setting {}", state.entity_id),
+            Change::CallService { domain, service, entity_id } => {
+                format!("Calling {}.{} on {}", domain, service, entity_id)
+            }
+        }
+    }
+
+    /// The list that shows what this changed, it's fetched again once the change is done.
+    pub fn resource(&self) -> Resource {
+        match self {
+            Change::Addon(..) => Resource::Addons,
+            Change::Backup(_) => Resource::Backups,
+            Change::SetState(..) | Change::CallService { .. } => Resource::States,
+        }
+    }
+}
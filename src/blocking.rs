@@ -0,0 +1,61 @@
+//! A synchronous wrapper around `HomeAssistantConnection`, for small daemons and scripts that don't
+//! want to bring their own async runtime. Each call blocks on a private single threaded runtime.
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use crate::error::Result;
+use crate::types::{
+    Event, HomeAssistantConnection, RequestEntityObject, RequestServiceStruct, RequestStateStruct,
+    Service, State,
+};
+
+pub struct BlockingConnection {
+    conn: Arc<RwLock<HomeAssistantConnection>>,
+    rt: tokio::runtime::Runtime,
+}
+
+impl BlockingConnection {
+    pub fn new(conn: Arc<RwLock<HomeAssistantConnection>>) -> Result<Self> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(BlockingConnection { conn, rt })
+    }
+
+    pub fn get_events(&self) -> Result<Vec<Event>> {
+        self.rt.block_on(self.conn().get_events())
+    }
+
+    pub fn fire_event(
+        &self,
+        event_type: String,
+        event_data: Option<impl serde::Serialize + std::fmt::Display>,
+    ) -> Result<String> {
+        self.rt.block_on(self.conn().fire_event(event_type, event_data))
+    }
+
+    pub fn get_services(&self) -> Result<Vec<Service>> {
+        self.rt.block_on(self.conn().get_services())
+    }
+
+    pub fn set_service(
+        &self,
+        service: &RequestServiceStruct<'_>,
+        entity: Option<&'_ RequestEntityObject<'_>>,
+    ) -> Result<serde_json::Value> {
+        self.rt.block_on(self.conn().set_service(service, entity))
+    }
+
+    pub fn get_states(&self) -> Result<Vec<State>> {
+        self.rt.block_on(self.conn().get_states())
+    }
+
+    pub fn set_state(&self, state: &State, payload: RequestStateStruct) -> Result<State> {
+        self.rt.block_on(self.conn().set_state(state, payload))
+    }
+
+    fn conn(&self) -> RwLockReadGuard<'_, HomeAssistantConnection> {
+        self.conn
+            .read()
+            .expect("Couldn't get the read lock on the connection")
+    }
+}
@@ -0,0 +1,90 @@
+//! The states, services & events the UI last fetched, kept in the XDG cache dir so the next launch
+//! has something to show straight away, and something to browse when the server can't be reached.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use haoscli::types::{Event, Service, State};
+
+#[derive(Serialize, Deserialize)]
+pub struct Cache {
+    /// Which instance these came from, a cache for a different one is ignored.
+    pub url: String,
+    pub saved: DateTime<Utc>,
+    pub events: Vec<Event>,
+    pub services: Vec<Service>,
+    pub states: Vec<State>,
+}
+
+/// Where the cache lives & the url of the instance the UI is talking to.
+pub struct CacheFile {
+    pub path: PathBuf,
+    pub url: String,
+}
+
+impl CacheFile {
+    /// The cache for this instance, if there's one that can be read.
+    pub fn load(&self) -> Option<Cache> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Couldn't read the cache {}: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        let cache: Cache = match serde_json::from_slice(&contents) {
+            Ok(cache) => cache,
+            Err(e) => {
+                warn!("Ignoring the cache {}, it couldn't be parsed: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        if cache.url != self.url {
+            info!("Ignoring the cache {}, it's for {}", self.path.display(), cache.url);
+            return None;
+        }
+        info!("Loaded the cache from {}, saved {}", self.path.display(), cache.saved);
+        Some(cache)
+    }
+
+    /// Writes next to the cache & moves it over, so quitting halfway through never leaves half a file.
+    pub fn save(&self, events: Vec<Event>, services: Vec<Service>, states: Vec<State>) -> io::Result<()> {
+        let cache = Cache {
+            url: self.url.clone(),
+            saved: Utc::now(),
+            events,
+            services,
+            states,
+        };
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        fs::write(&partial, serde_json::to_vec(&cache)?)?;
+        fs::rename(&partial, &self.path)?;
+        info!("Saved the cache to {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// `$XDG_CACHE_HOME/haoscli/cache.json`, falling back to `~/.cache`. Without a home it's left in
+/// the working directory like the log.
+pub fn default_cache_file() -> PathBuf {
+    let cache_dir = match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".cache"),
+            Err(_) => return PathBuf::from("haoscli-cache.json"),
+        },
+    };
+    cache_dir.join("haoscli").join("cache.json")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use haoscli::types::{Event, Service, State};
+
+use super::CacheFile;
+
+/// A fresh directory for a test to write into.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("haoscli-cache-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn cache_file(path: PathBuf) -> CacheFile {
+    CacheFile {
+        path,
+        url: String::from("http://homeassistant.local:8123"),
+    }
+}
+
+fn lists() -> (Vec<Event>, Vec<Service>, Vec<State>) {
+    let events = serde_json::from_value(json!([{"event": "state_changed", "listener_count": 3}])).unwrap();
+    let services = serde_json::from_value(json!([
+        {"domain": "light", "services": {"turn_on": {"description": "Turn on one or more lights"}}},
+    ]))
+    .unwrap();
+    let states = serde_json::from_value(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00",
+            "attributes": {"brightness": 120}},
+    ]))
+    .unwrap();
+    (events, services, states)
+}
+
+#[test]
+fn round_trip() {
+    // Left for the cache to make.
+    let cache = cache_file(scratch_dir("round-trip").join("haoscli").join("cache.json"));
+    let (events, services, states) = lists();
+    cache.save(events, services, states).unwrap();
+
+    let loaded = cache.load().unwrap();
+    assert_eq!(loaded.url, cache.url);
+    assert_eq!(loaded.events[0].event, "state_changed");
+    assert_eq!(loaded.services[0].domain, "light");
+    assert_eq!((loaded.states[0].entity_id.as_str(), loaded.states[0].state.as_str()), ("light.desk", "on"));
+    assert_eq!(loaded.states[0].attributes, json!({"brightness": 120}));
+}
+
+#[test]
+fn missing_or_corrupt_is_no_cache() {
+    let dir = scratch_dir("corrupt");
+    assert!(cache_file(dir.join("cache.json")).load().is_none());
+
+    fs::write(dir.join("cache.json"), br#"{"url": "http://homeassistant.local:8123", "saved": "#).unwrap();
+    assert!(cache_file(dir.join("cache.json")).load().is_none());
+    fs::write(dir.join("cache.json"), [0xff, 0xfe, 0x00]).unwrap();
+    assert!(cache_file(dir.join("cache.json")).load().is_none());
+}
+
+#[test]
+fn another_instances_cache_is_ignored() {
+    let path = scratch_dir("other-instance").join("cache.json");
+    let (events, services, states) = lists();
+    cache_file(path.clone()).save(events, services, states).unwrap();
+
+    let other = CacheFile {
+        path,
+        url: String::from("http://192.168.1.20:8123"),
+    };
+    assert!(other.load().is_none());
+}
+
+#[test]
+fn saved_by_moving_a_whole_file_over() {
+    let dir = scratch_dir("atomic");
+    let cache = cache_file(dir.join("cache.json"));
+    fs::write(dir.join("cache.json"), "what was there before").unwrap();
+    // Left over from a save that was killed halfway through.
+    fs::write(dir.join("cache.json.partial"), "half a").unwrap();
+
+    let (events, services, states) = lists();
+    cache.save(events, services, states).unwrap();
+    assert!(!dir.join("cache.json.partial").exists());
+    assert!(cache.load().is_some());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
@@ -0,0 +1,167 @@
+//! Recording the REST traffic to a file & playing it back without a server. A cassette is a JSON
+//! lines file, one request/response pair a line with the token scrubbed out, so it can be attached
+//! to a bug report or checked in next to a test.
+//!
+//! Only the REST API goes on a cassette, the websocket is left alone while recording and refused
+//! while replaying. Bodies that aren't text (IE: a backup tarball) are streamed to the caller as
+//! usual & left off, they replay as empty.
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// What the token is swapped for when a cassette is written.
+const SCRUBBED: &str = "<scrubbed>";
+
+/// One request & what Home Assistant answered it with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// Relative to the instance's url, IE: `/api/states`, so a cassette replays against any url.
+    pub path: String,
+    /// The body that was sent, if there was one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<serde_json::Value>,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// `None` when it wasn't text & so wasn't kept.
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Cassette {
+    /// Every request goes to the server as usual & is appended to the file once it's answered, so
+    /// it's all there even if haoscli doesn't get to quit cleanly.
+    Recording { path: PathBuf, file: Mutex<File> },
+    /// Nothing goes to the server, each request gets the first answer recorded for it that hasn't
+    /// been handed out yet. Once they're used up the last one is repeated, IE: for the UI polling.
+    Replaying {
+        interactions: Vec<Interaction>,
+        played: Mutex<Vec<bool>>,
+    },
+}
+
+impl Cassette {
+    /// Starts an empty cassette, whatever was in `path` before gets overwritten.
+    pub fn record(path: PathBuf) -> Result<Self> {
+        info!("Recording the requests to {}", path.display());
+        let file = Mutex::new(File::create(&path)?);
+        Ok(Cassette::Recording { path, file })
+    }
+
+    pub fn replay(path: &Path) -> Result<Self> {
+        let mut interactions = Vec::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction = serde_json::from_str(line).map_err(|e| {
+                Error::Cassette(format!("line {} of {} couldn't be parsed: {}", number + 1, path.display(), e))
+            })?;
+            interactions.push(interaction);
+        }
+        info!("Replaying {} requests from {}", interactions.len(), path.display());
+        let played = Mutex::new(vec![false; interactions.len()]);
+        Ok(Cassette::Replaying { interactions, played })
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, Cassette::Replaying { .. })
+    }
+
+    /// Sends `request` with `client` or answers it off the cassette. `base_url` is stripped off the
+    /// front of the url & `token` out of what gets written.
+    pub(crate) async fn send(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+        base_url: &str,
+        token: &str,
+    ) -> Result<reqwest::Response> {
+        let method = request.method().to_string();
+        let url = request.url().as_str();
+        let path = url.strip_prefix(base_url.trim_end_matches('/')).unwrap_or(url).to_string();
+        let sent = request.body().and_then(|body| body.as_bytes()).map(|body| {
+            serde_json::from_slice(body)
+                .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(body).into_owned()))
+        });
+
+        let interaction = match self {
+            Cassette::Recording { path: file_path, file } => {
+                let resp = client.execute(request).await?;
+                let content_type = resp
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(String::from);
+                let mut interaction = Interaction {
+                    method,
+                    path,
+                    request: sent,
+                    status: resp.status().as_u16(),
+                    content_type,
+                    body: None,
+                };
+                if !is_text(interaction.content_type.as_deref()) {
+                    debug!("Not keeping the body of {} {}, it isn't text", interaction.method, interaction.path);
+                    append(file_path, file, &interaction, token)?;
+                    return Ok(resp);
+                }
+                interaction.body = Some(String::from_utf8_lossy(&resp.bytes().await?).into_owned());
+                append(file_path, file, &interaction, token)?;
+                interaction
+            }
+            Cassette::Replaying { interactions, played } => {
+                let mut played = played.lock().expect("The cassette's lock was poisoned");
+                let matching: Vec<usize> = (0..interactions.len())
+                    .filter(|&idx| {
+                        let recorded = &interactions[idx];
+                        recorded.method == method && recorded.path == path && recorded.request == sent
+                    })
+                    .collect();
+                let Some(&idx) = matching.iter().find(|&&idx| !played[idx]).or_else(|| matching.last()) else {
+                    return Err(Error::Cassette(format!("{} {} isn't on the cassette", method, path)));
+                };
+                played[idx] = true;
+                interactions[idx].clone()
+            }
+        };
+        debug!("{} {} answered {} by the cassette", interaction.method, interaction.path, interaction.status);
+
+        let mut resp = reqwest::Response::from(
+            http::Response::builder()
+                .status(interaction.status)
+                .body(interaction.body.unwrap_or_default())
+                .map_err(|e| Error::Cassette(e.to_string()))?,
+        );
+        if let Some(content_type) = interaction.content_type.as_deref() {
+            if let Ok(value) = reqwest::header::HeaderValue::from_str(content_type) {
+                resp.headers_mut().insert(reqwest::header::CONTENT_TYPE, value);
+            }
+        }
+        Ok(resp)
+    }
+}
+
+/// JSON & plain text are kept, anything else is assumed to be a download.
+fn is_text(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| content_type.starts_with("text/") || content_type.contains("json"))
+}
+
+fn append(path: &Path, file: &Mutex<File>, interaction: &Interaction, token: &str) -> Result<()> {
+    let mut line = serde_json::to_string(interaction).map_err(|e| Error::Cassette(e.to_string()))?;
+    if !token.is_empty() {
+        line = line.replace(token, SCRUBBED);
+    }
+    line.push('\n');
+    let mut file = file.lock().expect("The cassette's lock was poisoned");
+    file.write_all(line.as_bytes())
+        .map_err(|e| Error::Cassette(format!("couldn't write to {}: {}", path.display(), e)))
+}
@@ -0,0 +1,163 @@
+//! `haoscli check` and `haoscli wait-for`, for scripts that need to know whether an entity is in
+//! the state they expect. Both say so through their exit code.
+use std::error::Error;
+use std::time::Duration;
+
+use clap::ArgMatches;
+use log::{info, warn};
+use tokio::time::Instant;
+
+use haoscli::condition::{Condition, Operator};
+use haoscli::error::Error as HaosError;
+use haoscli::types::{HomeAssistantConnection, State};
+
+use crate::cli::Exit;
+
+/// Same as `timeout(1)`, so scripts can tell a timeout apart from anything else going wrong.
+const TIMED_OUT: i32 = 124;
+
+/// Puts `--state`, `--above`, `--below` & every `--where` together, they all have to hold.
+fn conditions(matches: &ArgMatches) -> Result<Vec<Condition>, Box<dyn Error>> {
+    let mut conditions = Vec::new();
+    if let Some(state) = matches.get_one::<String>("state") {
+        conditions.push(Condition::new("state", Operator::Eq, state.as_str())?);
+    }
+    if let Some(above) = matches.get_one::<String>("above") {
+        conditions.push(Condition::new("state", Operator::Gt, above.as_str())?);
+    }
+    if let Some(below) = matches.get_one::<String>("below") {
+        conditions.push(Condition::new("state", Operator::Lt, below.as_str())?);
+    }
+    if let Some(wheres) = matches.get_many::<String>("where") {
+        for condition in wheres {
+            conditions.push(condition.parse()?);
+        }
+    }
+    if conditions.is_empty() {
+        return Err("Give at least one of --state, --above, --below or --where".into());
+    }
+    Ok(conditions)
+}
+
+/// Why each condition that doesn't hold, doesn't.
+fn failures(conditions: &[Condition], state: &State) -> Vec<String> {
+    conditions
+        .iter()
+        .filter(|condition| !condition.matches(state))
+        .map(|condition| condition.explain(state))
+        .collect()
+}
+
+/// Exits with 0 if every condition holds and 1 if one doesn't, or 3 if the entity couldn't be
+/// looked at. `--nagios` prints a plugin style status line and uses 2 for a failed check instead.
+pub async fn check(haos_conn: &HomeAssistantConnection, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let entity = matches
+        .get_one::<String>("ENTITY")
+        .expect("ENTITY is a required argument");
+    let conditions = conditions(matches)?;
+    let nagios = matches.contains_id("nagios");
+
+    let state = match haos_conn.get_state(entity).await {
+        Ok(state) => state,
+        Err(e) if nagios => {
+            println!("UNKNOWN - {}: {}", entity, e);
+            return Err(Exit::quiet(3).into());
+        }
+        Err(e) => return Err(Exit::new(3, format!("Couldn't get {}: {}", entity, e)).into()),
+    };
+
+    let failed = failures(&conditions, &state);
+    match (failed.is_empty(), nagios) {
+        (true, true) => println!("OK - {} is {}", entity, state.state),
+        (true, false) => (),
+        (false, true) => {
+            println!("CRITICAL - {}: {}", entity, failed.join(", "));
+            return Err(Exit::quiet(2).into());
+        }
+        (false, false) => return Err(Exit::new(1, format!("{}: {}", entity, failed.join(", "))).into()),
+    }
+    Ok(())
+}
+
+/// Polls the entity until every condition holds, exiting with 124 if `--timeout` runs out first.
+/// Hiccups talking to the server are waited out, but a refused token isn't going to fix itself.
+pub async fn wait_for(haos_conn: &HomeAssistantConnection, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let entity = matches
+        .get_one::<String>("ENTITY")
+        .expect("ENTITY is a required argument");
+    let conditions = conditions(matches)?;
+    let interval = parse_duration(matches.get_one::<String>("interval").expect("interval has a default"))?;
+    let deadline = match matches.get_one::<String>("timeout") {
+        Some(timeout) => Some(Instant::now() + parse_duration(timeout)?),
+        None => None,
+    };
+
+    let mut last_reason;
+    loop {
+        match haos_conn.get_state(entity).await {
+            Ok(state) => {
+                let failed = failures(&conditions, &state);
+                if failed.is_empty() {
+                    info!("{} is ready", entity);
+                    return Ok(());
+                }
+                last_reason = failed.join(", ");
+            }
+            Err(HaosError::Status(code, body)) if code.as_u16() == 401 || code.as_u16() == 403 => {
+                return Err(HaosError::Status(code, body).into());
+            }
+            Err(e) => {
+                warn!("Couldn't get {} while waiting on it: {}", entity, e);
+                last_reason = e.to_string();
+            }
+        }
+
+        let mut sleep_for = interval;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Exit::new(TIMED_OUT, format!("Timed out waiting on {}: {}", entity, last_reason)).into());
+            }
+            sleep_for = sleep_for.min(deadline - now);
+        }
+        tokio::time::sleep(sleep_for).await;
+    }
+}
+
+/// `90`, `90s`, `30m`, `1h30m` or `2d`. A bare number is seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let bad = || format!("{} isn't a duration, IE: 90s, 30m or 1h30m", text);
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(bad()),
+        };
+        let amount: u64 = number.parse().map_err(|_| bad())?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("{} is longer than haoscli can wait", text))?;
+        number.clear();
+    }
+    match number.is_empty() && !text.is_empty() {
+        true => Ok(Duration::from_secs(total)),
+        false => Err(bad()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
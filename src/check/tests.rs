@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use super::parse_duration;
+
+#[test]
+fn bare_numbers_are_seconds() {
+    assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration(" 0 "), Ok(Duration::from_secs(0)));
+}
+
+#[test]
+fn units() {
+    assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+    assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+    assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 24 * 60 * 60)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+    assert_eq!(parse_duration("1d2h3m4s"), Ok(Duration::from_secs(93_784)));
+}
+
+#[test]
+fn malformed() {
+    for text in ["", "m", "10x", "1.5h", "h30", "30m10", "-5s", "1 h"] {
+        assert!(parse_duration(text).is_err(), "{:?} parsed", text);
+    }
+}
+
+#[test]
+fn too_long_is_an_error_not_a_panic() {
+    assert!(parse_duration("99999999999999999999s").is_err());
+    assert!(parse_duration("18446744073709551615d").is_err());
+    assert!(parse_duration("18446744073709551615s1s").is_err());
+}
@@ -0,0 +1,655 @@
+//! The non-interactive side of haoscli. Each subcommand does its one thing against the Home
+//! Assistant instance, prints the result and exits rather than bringing up the UI.
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Read};
+use std::path::PathBuf;
+
+use clap::{arg, ArgMatches, Command};
+
+use haoscli::types::{HomeAssistantConnection, NewBackup, State};
+
+use crate::output::{self, OutputFormat, Table};
+use crate::{check, repl, snapshot, watch};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// Where `backup create --password` looks for the password before asking for it.
+const BACKUP_PASSWORD_ENV: &str = "HAOS_BACKUP_PASSWORD";
+
+/// Returned by the subcommands whose exit code means something, IE: `check` exits 1 when the
+/// condition doesn't hold. `main` prints the message, if there is one, and exits with `code`.
+#[derive(Debug)]
+pub struct Exit {
+    pub code: i32,
+    pub message: Option<String>,
+}
+
+impl Exit {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Exit {
+            code,
+            message: Some(message.into()),
+        }
+    }
+
+    /// For when whatever needed saying already went to stdout.
+    pub fn quiet(code: i32) -> Self {
+        Exit { code, message: None }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}", message),
+            None => write!(f, "exited with {}", self.code),
+        }
+    }
+}
+
+impl Error for Exit {}
+
+/// `--entity <GLOB>` & `--domain <DOMAIN>`, for the subcommands that work on a bunch of entities.
+pub struct EntityFilter {
+    entity: Option<String>,
+    domain: Option<String>,
+}
+
+impl EntityFilter {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        EntityFilter {
+            entity: matches.get_one::<String>("entity").cloned(),
+            domain: matches.get_one::<String>("domain").cloned(),
+        }
+    }
+
+    /// Events that aren't about an entity only get through when nothing was asked for.
+    pub fn matches(&self, entity_id: Option<&str>) -> bool {
+        if self.entity.is_none() && self.domain.is_none() {
+            return true;
+        }
+        let Some(entity_id) = entity_id else {
+            return false;
+        };
+        let entity_ok = match &self.entity {
+            Some(pattern) => glob_match(pattern, entity_id),
+            None => true,
+        };
+        let domain_ok = match &self.domain {
+            Some(domain) => entity_id.split_once('.').map(|(d, _)| d) == Some(domain.as_str()),
+            None => true,
+        };
+        entity_ok && domain_ok
+    }
+}
+
+/// The arguments `EntityFilter` reads.
+fn filter_args<'help>() -> Vec<clap::Arg<'help>> {
+    vec![
+        arg!(--entity <GLOB> "Only these entities, IE: 'light.*'").required(false),
+        arg!(--domain <DOMAIN> "Only entities in this domain").required(false),
+    ]
+}
+
+/// `--state`, `--above`, `--below` & `--where`, shared by `check` and `wait-for`.
+fn condition_args<'help>() -> Vec<clap::Arg<'help>> {
+    vec![
+        arg!(--state <VALUE> "The state has to be this").required(false),
+        arg!(--above <NUMBER> "The state has to be a number above this").required(false),
+        arg!(--below <NUMBER> "The state has to be a number below this").required(false),
+        arg!(--where <CONDITION> "IE: 'attributes.brightness>=100', can be given more than once")
+            .required(false)
+            .multiple_occurrences(true),
+    ]
+}
+
+/// The global arguments that go along with the subcommands.
+pub fn args<'help>() -> Vec<clap::Arg<'help>> {
+    vec![
+        arg!(--output <FORMAT> "How to print results")
+            .required(false)
+            .global(true)
+            .possible_values(["table", "json", "yaml"])
+            .default_value("table"),
+        arg!(--record <FILE> "Write every request & its response to this cassette, with the token scrubbed")
+            .required(false)
+            .global(true)
+            .value_parser(clap::value_parser!(PathBuf)),
+        arg!(--replay <FILE> "Answer every request from this cassette instead of the server")
+            .required(false)
+            .global(true)
+            .conflicts_with("record")
+            .value_parser(clap::value_parser!(PathBuf)),
+    ]
+}
+
+/// The subcommands, hung off of the main command in `main`.
+pub fn subcommands<'help>() -> Vec<Command<'help>> {
+    vec![
+        Command::new("states")
+            .about("Look at entity states")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("list")
+                    .about("List every entity and its state")
+                    .arg(arg!(--domain <DOMAIN> "Only entities in this domain, IE: light").required(false)),
+            )
+            .subcommand(
+                Command::new("get")
+                    .about("Show one entity's state and attributes")
+                    .arg(arg!(<ENTITY> "IE: light.desk")),
+            ),
+        Command::new("services")
+            .about("Look at the services that can be called")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("list")
+                    .about("List every service")
+                    .arg(arg!(--domain <DOMAIN> "Only services in this domain, IE: light").required(false)),
+            ),
+        Command::new("call")
+            .about("Call a service, IE: haoscli call light.turn_on --target light.desk --data '{\"brightness\": 120}'")
+            .arg(arg!(<SERVICE> "domain.service, IE: light.turn_on"))
+            .arg(
+                arg!(--target <ENTITY> "Entity to call it on, can be given more than once")
+                    .required(false)
+                    .multiple_occurrences(true),
+            )
+            .arg(arg!(--data <JSON> "Service data as a json object").required(false))
+            .arg(arg!([FIELDS] "Service data as field=value, IE: brightness=120").multiple_values(true)),
+        Command::new("events")
+            .about("List or fire events")
+            .subcommand_required(true)
+            .subcommand(Command::new("list").about("List the events and how many listeners each has"))
+            .subcommand(
+                Command::new("fire")
+                    .about("Fire an event")
+                    .arg(arg!(<EVENT> "The event type"))
+                    .arg(arg!(--data <JSON> "Event data as a json object").required(false)),
+            ),
+        Command::new("set-state")
+            .about("Set the state Home Assistant holds for an entity (doesn't talk to the device)")
+            .arg(arg!(<ENTITY> "IE: sensor.fake_temperature"))
+            .arg(arg!(<STATE> "The new state"))
+            .arg(arg!(--attributes <JSON> "Attributes as a json object, replaces the existing ones").required(false)),
+        Command::new("template")
+            .about("Work with Jinja templates")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("render")
+                    .about("Render a template on the server")
+                    .arg(arg!(<TEMPLATE> "The template, or - to read it from stdin")),
+            ),
+        Command::new("watch")
+            .about("Print every state change as a line of json until killed")
+            .args(filter_args())
+            .arg(arg!(--event <TYPE> "Watch this event type instead of state_changed").required(false))
+            .arg(
+                arg!(--interval <SECS> "How often to poll when the websocket isn't there")
+                    .required(false)
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("5"),
+            ),
+        Command::new("check")
+            .about("Exit 0 if the entity matches the conditions, 1 if it doesn't & 3 if it can't be looked at")
+            .arg(arg!(<ENTITY> "IE: binary_sensor.door"))
+            .args(condition_args())
+            .arg(arg!(--nagios "Print a Nagios plugin style status line, a failed check exits with 2")),
+        Command::new("wait-for")
+            .about("Wait until the entity matches the conditions, exits with 124 if --timeout runs out")
+            .arg(arg!(<ENTITY> "IE: sensor.washer"))
+            .args(condition_args())
+            .arg(arg!(--timeout <DURATION> "Give up after this long, IE: 30m").required(false))
+            .arg(
+                arg!(--interval <DURATION> "How often to look")
+                    .required(false)
+                    .default_value("5s"),
+            ),
+        Command::new("snapshot")
+            .about("Save every entity's state to a file, diff two of them or put one back")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("save")
+                    .about("Write the states to a json, yaml or csv file")
+                    .arg(arg!(<FILE> "Where to write it, the extension picks the format"))
+                    .arg(
+                        arg!(--format <FORMAT> "Use this format whatever the extension")
+                            .required(false)
+                            .possible_values(["json", "yaml", "csv"]),
+                    )
+                    .args(filter_args()),
+            )
+            .subcommand(
+                Command::new("diff")
+                    .about("Show what changed between two snapshots, or between one and right now")
+                    .arg(arg!(<BEFORE> "The older snapshot"))
+                    .arg(arg!([AFTER] "The newer snapshot, defaults to the live states"))
+                    .args(filter_args()),
+            )
+            .subcommand(
+                Command::new("restore")
+                    .about("Put lights, switches & the like back how a snapshot has them through scene.apply")
+                    .arg(arg!(<FILE> "The snapshot to restore"))
+                    .arg(arg!(--"dry-run" "Only show what would be set"))
+                    .args(filter_args()),
+            ),
+        Command::new("discover")
+            .about("Look for Home Assistant instances on the LAN over mDNS, doesn't need a config")
+            .arg(
+                arg!(--timeout <DURATION> "How long to listen for")
+                    .required(false)
+                    .default_value("3s"),
+            ),
+        Command::new("repl").about("A shell taking these same commands, with history & tab completion"),
+        Command::new("backup")
+            .about("List, create, delete and download backups through the Supervisor")
+            .subcommand_required(true)
+            .subcommand(Command::new("list").about("List the backups"))
+            .subcommand(
+                Command::new("create")
+                    .about("Create a backup, full unless --partial is given")
+                    .arg(arg!(--name <NAME> "Name of the backup").required(false))
+                    .arg(arg!(--password "Protect the backup with a password, taken from HAOS_BACKUP_PASSWORD or asked for"))
+                    .arg(arg!(--partial "Only back up the given add-ons and folders"))
+                    .arg(arg!(--homeassistant "Include the Home Assistant config in a partial backup"))
+                    .arg(
+                        arg!(--addon <SLUG> "Add-on to include in a partial backup")
+                            .required(false)
+                            .multiple_occurrences(true),
+                    )
+                    .arg(
+                        arg!(--folder <FOLDER> "Folder to include in a partial backup, IE: share")
+                            .required(false)
+                            .multiple_occurrences(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("delete")
+                    .about("Delete a backup")
+                    .arg(arg!(<SLUG> "The backup to delete")),
+            )
+            .subcommand(
+                Command::new("download")
+                    .about("Download a backup tarball")
+                    .arg(arg!(<SLUG> "The backup to download"))
+                    .arg(arg!(-f --file <FILE> "Where to write it, defaults to <SLUG>.tar").required(false))
+                    .arg(arg!(--force "Overwrite the file if it's already there")),
+            ),
+    ]
+}
+
+/// What `--output` asked for.
+pub fn output_format(matches: &ArgMatches) -> OutputFormat {
+    OutputFormat::from_arg(
+        matches
+            .get_one::<String>("output")
+            .map(String::as_str)
+            .unwrap_or("table"),
+    )
+}
+
+/// Runs whichever subcommand was picked.
+pub async fn run(haos_conn: &HomeAssistantConnection, matches: &ArgMatches) -> CliResult {
+    let format = output_format(matches);
+    match matches.subcommand() {
+        Some(("states", sub)) => states(haos_conn, sub, format).await,
+        Some(("services", sub)) => services(haos_conn, sub, format).await,
+        Some(("call", sub)) => call(haos_conn, sub, format).await,
+        Some(("events", sub)) => events(haos_conn, sub, format).await,
+        Some(("set-state", sub)) => {
+            let entity = required(sub, "ENTITY");
+            let attributes = json_arg(sub, "attributes")?;
+            let state = haos_conn
+                .post_state(entity, required(sub, "STATE"), attributes.as_ref())
+                .await?;
+            print_state(format, &state)
+        }
+        Some(("template", sub)) => template(haos_conn, sub, format).await,
+        Some(("watch", sub)) => watch::run(haos_conn, sub).await,
+        Some(("check", sub)) => check::check(haos_conn, sub).await,
+        Some(("wait-for", sub)) => check::wait_for(haos_conn, sub).await,
+        Some(("snapshot", sub)) => snapshot::run(haos_conn, sub, format).await,
+        Some(("discover", sub)) => discover(sub, format).await,
+        Some(("repl", _)) => repl::run(haos_conn).await,
+        Some(("backup", sub)) => backup(haos_conn, sub, format).await,
+        _ => unreachable!("clap only lets through the subcommands we defined"),
+    }
+}
+
+/// Runs before the config is read, there's nothing to connect to yet.
+pub async fn discover(matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    let timeout = check::parse_duration(required(matches, "timeout"))?;
+    let instances = haoscli::discovery::discover(timeout).await?;
+    if instances.is_empty() && format == OutputFormat::Table {
+        eprintln!("Nothing answered in {:?}, is this machine on the same network?", timeout);
+        return Ok(());
+    }
+    output::print(format, &instances, || Table {
+        header: vec!["NAME", "VERSION", "URL"],
+        rows: instances
+            .iter()
+            .map(|instance| {
+                vec![
+                    instance.name.clone(),
+                    instance.version.clone().unwrap_or_default(),
+                    instance.base_url.clone(),
+                ]
+            })
+            .collect(),
+    })?;
+    Ok(())
+}
+
+async fn states(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    match matches.subcommand() {
+        Some(("list", sub)) => {
+            let mut states = haos_conn.get_states().await?;
+            if let Some(domain) = sub.get_one::<String>("domain") {
+                let prefix = format!("{}.", domain);
+                states.retain(|state| state.entity_id.starts_with(&prefix));
+            }
+            output::print(format, &states, || Table {
+                header: vec!["ENTITY", "STATE", "LAST CHANGED"],
+                rows: states
+                    .iter()
+                    .map(|state| {
+                        vec![
+                            state.entity_id.clone(),
+                            state.state.clone(),
+                            state.last_changed.to_rfc3339(),
+                        ]
+                    })
+                    .collect(),
+            })?;
+        }
+        Some(("get", sub)) => {
+            let state = haos_conn.get_state(required(sub, "ENTITY")).await?;
+            print_state(format, &state)?;
+        }
+        _ => unreachable!("clap only lets through the subcommands we defined"),
+    }
+    Ok(())
+}
+
+async fn services(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    let Some(("list", sub)) = matches.subcommand() else {
+        unreachable!("clap only lets through the subcommands we defined")
+    };
+    let mut services = haos_conn.get_services().await?;
+    if let Some(domain) = sub.get_one::<String>("domain") {
+        services.retain(|service| &service.domain == domain);
+    }
+    output::print(format, &services, || {
+        let mut rows = Vec::new();
+        for service in &services {
+            let Some(map) = service.services.as_object() else {
+                continue;
+            };
+            for (name, details) in map {
+                let description = details
+                    .get("description")
+                    .and_then(|desc| desc.as_str())
+                    .unwrap_or_default();
+                rows.push(vec![format!("{}.{}", service.domain, name), description.to_string()]);
+            }
+        }
+        Table {
+            header: vec!["SERVICE", "DESCRIPTION"],
+            rows,
+        }
+    })?;
+    Ok(())
+}
+
+async fn call(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    let service = required(matches, "SERVICE");
+    let (domain, service) = service
+        .split_once('.')
+        .ok_or_else(|| format!("{} should look like domain.service", service))?;
+
+    let mut data = json_arg(matches, "data")?.unwrap_or_else(|| serde_json::json!({}));
+    if !data.is_object() {
+        return Err("--data has to be a json object".into());
+    }
+    // Values that parse as json (numbers, true, lists...) are sent as such, the rest as strings.
+    for field in strings(matches, "FIELDS") {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("{} should look like field=value", field))?;
+        data[key] = serde_json::from_str(value).unwrap_or_else(|_| serde_json::json!(value));
+    }
+    let targets = strings(matches, "target");
+    match targets.len() {
+        0 => (),
+        1 => data["entity_id"] = serde_json::json!(targets[0]),
+        _ => data["entity_id"] = serde_json::json!(targets),
+    }
+
+    let changed = haos_conn.call_service(domain, service, &data).await?;
+    print_states(format, &changed)
+}
+
+async fn events(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            let events = haos_conn.get_events().await?;
+            output::print(format, &events, || Table {
+                header: vec!["EVENT", "LISTENERS"],
+                rows: events
+                    .iter()
+                    .map(|event| vec![event.event.clone(), event.listener_count.to_string()])
+                    .collect(),
+            })?;
+        }
+        Some(("fire", sub)) => {
+            let event = required(sub, "EVENT");
+            let message = haos_conn
+                .fire_event(event.to_string(), json_arg(sub, "data")?)
+                .await?;
+            let result = serde_json::json!({ "message": message });
+            output::print(format, &result, || Table {
+                header: vec!["MESSAGE"],
+                rows: vec![vec![message.clone()]],
+            })?;
+        }
+        _ => unreachable!("clap only lets through the subcommands we defined"),
+    }
+    Ok(())
+}
+
+async fn template(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    let Some(("render", sub)) = matches.subcommand() else {
+        unreachable!("clap only lets through the subcommands we defined")
+    };
+    let template = match required(sub, "TEMPLATE") {
+        "-" => {
+            let mut template = String::new();
+            io::stdin().read_to_string(&mut template)?;
+            template
+        }
+        template => template.to_string(),
+    };
+    let rendered = haos_conn.render_template(&template).await?;
+    match format {
+        // The rendered text is printed as is so it can be used straight from a shell.
+        OutputFormat::Table => println!("{}", rendered),
+        _ => output::print(format, &serde_json::json!({ "result": rendered }), || Table {
+            header: vec![],
+            rows: vec![],
+        })?,
+    }
+    Ok(())
+}
+
+async fn backup(haos_conn: &HomeAssistantConnection, matches: &ArgMatches, format: OutputFormat) -> CliResult {
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            let backups = haos_conn.get_backups().await?;
+            output::print(format, &backups, || Table {
+                header: vec!["SLUG", "NAME", "DATE", "TYPE", "SIZE (MB)", "PROTECTED"],
+                rows: backups
+                    .iter()
+                    .map(|backup| {
+                        vec![
+                            backup.slug.clone(),
+                            backup.name.clone(),
+                            backup.date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                            backup.kind.clone(),
+                            format!("{:.2}", backup.size),
+                            backup.protected.to_string(),
+                        ]
+                    })
+                    .collect(),
+            })?;
+        }
+        Some(("create", sub)) => {
+            let new_backup = NewBackup {
+                partial: sub.contains_id("partial"),
+                name: sub.get_one::<String>("name").cloned(),
+                password: match sub.contains_id("password") {
+                    true => Some(backup_password()?),
+                    false => None,
+                },
+                homeassistant: sub.contains_id("homeassistant").then_some(true),
+                addons: strings(sub, "addon"),
+                folders: strings(sub, "folder"),
+            };
+            let slug = haos_conn.create_backup(&new_backup).await?;
+            println!("{}", slug);
+        }
+        Some(("delete", sub)) => {
+            haos_conn.delete_backup(required(sub, "SLUG")).await?;
+        }
+        Some(("download", sub)) => {
+            let slug = required(sub, "SLUG");
+            let path = match sub.get_one::<String>("file") {
+                Some(v) => PathBuf::from(v),
+                None => PathBuf::from(format!("{}.tar", slug)),
+            };
+            if path.exists() && !sub.contains_id("force") {
+                return Err(format!("{} is already there, pass --force to overwrite it", path.display()).into());
+            }
+            let written = haos_conn.download_backup(slug, &path).await?;
+            eprintln!("Wrote {} bytes to {}", written, path.display());
+        }
+        _ => unreachable!("clap only lets through the subcommands we defined"),
+    }
+    Ok(())
+}
+
+fn print_state(format: OutputFormat, state: &State) -> CliResult {
+    output::print(format, state, || {
+        let mut rows = vec![
+            vec![String::from("entity_id"), state.entity_id.clone()],
+            vec![String::from("state"), state.state.clone()],
+            vec![String::from("last_changed"), state.last_changed.to_rfc3339()],
+        ];
+        if let Some(attributes) = state.attributes.as_object() {
+            for (key, value) in attributes {
+                let value = match value {
+                    serde_json::Value::String(v) => v.clone(),
+                    other => other.to_string(),
+                };
+                rows.push(vec![format!("attributes.{}", key), value]);
+            }
+        }
+        Table {
+            header: vec!["FIELD", "VALUE"],
+            rows,
+        }
+    })?;
+    Ok(())
+}
+
+fn print_states(format: OutputFormat, states: &[State]) -> CliResult {
+    output::print(format, &states, || Table {
+        header: vec!["ENTITY", "STATE"],
+        rows: states
+            .iter()
+            .map(|state| vec![state.entity_id.clone(), state.state.clone()])
+            .collect(),
+    })?;
+    Ok(())
+}
+
+/// Asked for rather than taken as an argument so it doesn't end up in the shell history or `ps`.
+/// Piped in it's the first line of stdin, at a terminal it's asked for twice as a typo would leave
+/// the backup locked for good.
+fn backup_password() -> Result<String, Box<dyn Error>> {
+    let password = match std::env::var(BACKUP_PASSWORD_ENV) {
+        Ok(password) => password,
+        Err(_) if !io::stdin().is_terminal() => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+        Err(_) => {
+            let password = rpassword::prompt_password("Backup password: ")?;
+            if rpassword::prompt_password("Again: ")? != password {
+                return Err("The passwords didn't match".into());
+            }
+            password
+        }
+    };
+    match password.is_empty() {
+        true => Err(format!("No password given, type one in or set {}", BACKUP_PASSWORD_ENV).into()),
+        false => Ok(password),
+    }
+}
+
+/// For the positional arguments clap has already made sure are there.
+fn required<'a>(matches: &'a ArgMatches, id: &str) -> &'a str {
+    matches
+        .get_one::<String>(id)
+        .map(String::as_str)
+        .unwrap_or_else(|| unreachable!("{} is a required argument", id))
+}
+
+/// Parses an argument that holds json, IE: `--data '{"brightness": 120}'`.
+fn json_arg(matches: &ArgMatches, id: &str) -> Result<Option<serde_json::Value>, Box<dyn Error>> {
+    match matches.get_one::<String>(id) {
+        Some(v) => serde_json::from_str(v)
+            .map(Some)
+            .map_err(|e| format!("--{} isn't valid json: {}", id, e).into()),
+        None => Ok(None),
+    }
+}
+
+/// Collects every value given for an argument that can be repeated.
+fn strings(matches: &ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map(|vals| vals.cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Shell style matching where `*` is any run of characters & `?` is exactly one, IE: `light.*`.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was and how much of the text it's eaten, so we can back up to it.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
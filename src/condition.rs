@@ -0,0 +1,168 @@
+//! Conditions on an entity's state, IE: `state==off` or `attributes.brightness>=100`, for the
+//! scripts that want to wait on or check something.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::types::State;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+        }
+    }
+}
+
+/// `<path> <operator> <value>`. The path is `state`, `entity_id`, `last_changed` or
+/// `attributes.` followed by keys/array indexes, IE: `attributes.hvac_modes.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub path: Vec<String>,
+    pub operator: Operator,
+    pub value: String,
+}
+
+impl Condition {
+    pub fn new(path: &str, operator: Operator, value: impl Into<String>) -> Result<Self> {
+        let path: Vec<String> = path.trim().split('.').map(String::from).collect();
+        match path[0].as_str() {
+            "state" | "entity_id" | "last_changed" if path.len() == 1 => (),
+            "attributes" if path.len() > 1 && path.iter().all(|key| !key.is_empty()) => (),
+            _ => {
+                return Err(Error::Condition(format!(
+                    "{} isn't something to check, use state, entity_id, last_changed or attributes.<name>",
+                    path.join(".")
+                )))
+            }
+        }
+        Ok(Condition {
+            path,
+            operator,
+            value: value.into(),
+        })
+    }
+
+    /// Whether the condition holds for `state`. Missing attributes never match, and neither do
+    /// `>`, `<` & co. when either side isn't a number.
+    pub fn matches(&self, state: &State) -> bool {
+        let Some(actual) = self.actual(state) else {
+            return false;
+        };
+        let numbers = (as_number(&actual), self.value.trim().parse::<f64>().ok());
+        match (self.operator, numbers) {
+            (Operator::Eq, (Some(a), Some(b))) => a == b,
+            (Operator::Ne, (Some(a), Some(b))) => a != b,
+            (Operator::Eq, _) => as_text(&actual) == self.value,
+            (Operator::Ne, _) => as_text(&actual) != self.value,
+            (Operator::Gt, (Some(a), Some(b))) => a > b,
+            (Operator::Ge, (Some(a), Some(b))) => a >= b,
+            (Operator::Lt, (Some(a), Some(b))) => a < b,
+            (Operator::Le, (Some(a), Some(b))) => a <= b,
+            _ => false,
+        }
+    }
+
+    /// The value the condition looks at, `None` if the attribute isn't there.
+    pub fn actual(&self, state: &State) -> Option<serde_json::Value> {
+        match self.path[0].as_str() {
+            "state" => Some(serde_json::Value::String(state.state.clone())),
+            "entity_id" => Some(serde_json::Value::String(state.entity_id.clone())),
+            "last_changed" => Some(serde_json::Value::String(state.last_changed.to_rfc3339())),
+            _ => {
+                let mut value = &state.attributes;
+                for key in &self.path[1..] {
+                    value = match value {
+                        serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                        other => other.get(key)?,
+                    };
+                }
+                Some(value.clone())
+            }
+        }
+    }
+
+    /// Says what the value actually was, IE: `attributes.brightness is 80, wanted >= 100`.
+    pub fn explain(&self, state: &State) -> String {
+        match self.actual(state) {
+            Some(actual) => format!(
+                "{} is {}, wanted {} {}",
+                self.path.join("."),
+                as_text(&actual),
+                self.operator.as_str(),
+                self.value
+            ),
+            None => format!("{} isn't there", self.path.join(".")),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    /// Parses `attributes.brightness>=100`, `state!=unavailable` and so on. A lone `=` works the
+    /// same as `==`.
+    fn from_str(s: &str) -> Result<Self> {
+        // Two character operators go first so `>=` isn't read as `>` followed by `=100`.
+        let operators = [
+            ("==", Operator::Eq),
+            ("!=", Operator::Ne),
+            (">=", Operator::Ge),
+            ("<=", Operator::Le),
+            (">", Operator::Gt),
+            ("<", Operator::Lt),
+            ("=", Operator::Eq),
+        ];
+        let found = operators
+            .iter()
+            .filter_map(|(op, operator)| s.find(op).map(|idx| (idx, *op, *operator)))
+            .min_by_key(|(idx, op, _)| (*idx, std::cmp::Reverse(op.len())));
+        match found {
+            Some((idx, op, operator)) => Condition::new(&s[..idx], operator, s[idx + op.len()..].trim()),
+            None => Err(Error::Condition(format!(
+                "{} doesn't have an operator in it, IE: state==on or attributes.brightness>100",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.path.join("."), self.operator.as_str(), self.value)
+    }
+}
+
+fn as_number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(v) => v.as_f64(),
+        serde_json::Value::String(v) => v.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Strings without their quotes, everything else as json.
+fn as_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(v) => v.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
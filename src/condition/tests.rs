@@ -0,0 +1,76 @@
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+
+use super::{Condition, Operator};
+use crate::types::State;
+
+fn state(value: &str, attributes: serde_json::Value) -> State {
+    State {
+        entity_id: String::from("light.desk"),
+        state: String::from(value),
+        last_changed: Utc.ymd(2024, 1, 1).and_hms(10, 0, 0),
+        attributes,
+    }
+}
+
+fn holds(condition: &str, state: &State) -> bool {
+    condition.parse::<Condition>().unwrap().matches(state)
+}
+
+#[test]
+fn parses_each_operator() {
+    for (text, operator) in [
+        ("state==on", Operator::Eq),
+        ("state=on", Operator::Eq),
+        ("state!=on", Operator::Ne),
+        ("state>1", Operator::Gt),
+        ("state>=1", Operator::Ge),
+        ("state<1", Operator::Lt),
+        ("state<=1", Operator::Le),
+    ] {
+        let condition: Condition = text.parse().unwrap();
+        assert_eq!(condition.operator, operator, "{}", text);
+        assert_eq!(condition.path, vec!["state"]);
+    }
+    let condition: Condition = " attributes.brightness >= 100 ".parse().unwrap();
+    assert_eq!(condition.path, vec!["attributes", "brightness"]);
+    assert_eq!(condition.value, "100");
+}
+
+#[test]
+fn malformed() {
+    for text in ["state", "", "colour==red", "attributes==1", "attributes.==1", "state.x==1", "==on"] {
+        assert!(text.parse::<Condition>().is_err(), "{:?} parsed", text);
+    }
+}
+
+#[test]
+fn compares_numbers_as_numbers() {
+    let dimmed = state("on", json!({"brightness": 80}));
+    assert!(holds("attributes.brightness<100", &dimmed));
+    assert!(holds("attributes.brightness<=80", &dimmed));
+    assert!(holds("attributes.brightness==80.0", &dimmed));
+    assert!(!holds("attributes.brightness>=100", &dimmed));
+    // 9 < 10 as numbers, even though "9" > "10" as text.
+    assert!(holds("state<10", &state("9", json!({}))));
+}
+
+#[test]
+fn compares_everything_else_as_text() {
+    let on = state("on", json!({"hvac_modes": ["heat", "off"], "friendly_name": "Desk"}));
+    assert!(holds("state==on", &on));
+    assert!(holds("state!=unavailable", &on));
+    assert!(holds("entity_id==light.desk", &on));
+    assert!(holds("attributes.hvac_modes.1==off", &on));
+    assert!(holds("last_changed==2024-01-01T10:00:00+00:00", &on));
+    // Ordering needs numbers on both sides.
+    assert!(!holds("state>off", &on));
+}
+
+#[test]
+fn missing_attributes_never_match() {
+    let bare = state("on", json!({}));
+    assert!(!holds("attributes.brightness==0", &bare));
+    assert!(!holds("attributes.brightness!=0", &bare));
+    assert!(!holds("attributes.hvac_modes.5==off", &state("on", json!({"hvac_modes": ["heat"]}))));
+}
@@ -0,0 +1,313 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+use log::info;
+
+use serde::Deserialize;
+
+/// Overrides `url` from the config file.
+const URL_ENV: &str = "HAOS_URL";
+/// Overrides every way of giving the token in the config file.
+const TOKEN_ENV: &str = "HAOS_TOKEN";
+
+/// Anything faster than this just hammers the server.
+const MIN_POLL_RATE: u64 = 100;
+
+/// Everything but `url` (and the token) can be left out.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Can be left out if `HAOS_URL` is set.
+    url: Option<String>,
+    /// The token, written right into the file. Only one of `token`, `token_file` and
+    /// `token_command` can be set, and `HAOS_TOKEN` beats all of them.
+    token: Option<String>,
+    /// A file holding nothing but the token.
+    token_file: Option<PathBuf>,
+    /// A command whose stdout is the token, IE: `pass show home-assistant`. Run through `sh -c`.
+    token_command: Option<String>,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub log_level: LogLevel,
+    /// Where the log goes, `$XDG_STATE_HOME/haoscli/haoscli.log` if it's not set.
+    log_file: Option<PathBuf>,
+    /// Where the UI keeps the last states, services & events it fetched,
+    /// `$XDG_CACHE_HOME/haoscli/cache.json` if it's not set.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    cache_file: Option<PathBuf>,
+    /// How big the log can get before it's rotated, in kilobytes. 0 never rotates.
+    #[serde(default = "default_log_max_size")]
+    pub log_max_size: u64,
+    /// How long the UI waits between fetches, in milliseconds.
+    #[serde(default = "default_poll_rate")]
+    pub poll_rate: u64,
+    /// Per list overrides of `poll_rate`.
+    #[serde(default)]
+    pub refresh: Refresh,
+    /// Hold on to the changes made in the UI while Home Assistant can't be reached & send them once
+    /// it's back, rather than sending them anyway (which fails).
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    #[serde(default)]
+    pub queue_offline: bool,
+    /// How old a queued change can get, in seconds, before it's dropped instead of sent.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    #[serde(default = "default_queue_max_age")]
+    pub queue_max_age: u64,
+    /// PEM file of extra CA certificates to trust, for self-signed setups on the LAN.
+    pub ca_bundle: Option<PathBuf>,
+    /// Turns off certificate validation, but only if this matches the host in `url`.
+    pub accept_invalid_certs_for: Option<String>,
+    /// Client certificate & PKCS#8 key (both PEM) for mutual TLS behind a reverse proxy.
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// HTTP(S) proxy to send everything through.
+    pub proxy: Option<String>,
+}
+
+/// How often the UI fetches each list again, in milliseconds. Anything left out goes at `poll_rate`,
+/// except the energy statistics which only move once an hour or so & default to 5 minutes.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Refresh {
+    pub events: Option<u64>,
+    pub services: Option<u64>,
+    pub states: Option<u64>,
+    pub addons: Option<u64>,
+    pub addon_logs: Option<u64>,
+    pub backups: Option<u64>,
+    pub energy: Option<u64>,
+}
+
+/// Either case works, IE: `Info` or `info`.
+#[derive(Deserialize, Default)]
+pub enum LogLevel {
+    #[serde(alias = "off")]
+    Off,
+    #[serde(alias = "error")]
+    Error,
+    #[default]
+    #[serde(alias = "warn")]
+    Warn,
+    #[serde(alias = "info")]
+    Info,
+    #[serde(alias = "debug")]
+    Debug,
+    #[serde(alias = "trace")]
+    Trace,
+}
+
+fn default_client_id() -> String {
+    String::from("haoscli")
+}
+
+fn default_poll_rate() -> u64 {
+    1000
+}
+
+fn default_queue_max_age() -> u64 {
+    300
+}
+
+fn default_log_max_size() -> u64 {
+    1024
+}
+
+impl Config {
+    /// Reads & checks the config, the error says what's wrong with it and where.
+    pub fn new(args: Args) -> Result<Self, String> {
+        let config = read_toml(&args.config_path)?;
+        config
+            .validate()
+            .map_err(|e| format!("{}: {}", args.config_path, e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.url {
+            check_url(url).map_err(|e| format!("url {}", e))?;
+        }
+        if self.poll_rate < MIN_POLL_RATE {
+            return Err(format!(
+                "poll_rate is {}, it has to be at least {} (it's in milliseconds)",
+                self.poll_rate, MIN_POLL_RATE
+            ));
+        }
+        let refresh = &self.refresh;
+        for (name, rate) in [
+            ("events", refresh.events),
+            ("services", refresh.services),
+            ("states", refresh.states),
+            ("addons", refresh.addons),
+            ("addon_logs", refresh.addon_logs),
+            ("backups", refresh.backups),
+            ("energy", refresh.energy),
+        ] {
+            if let Some(rate) = rate.filter(|rate| *rate < MIN_POLL_RATE) {
+                return Err(format!(
+                    "refresh.{} is {}, it has to be at least {} (it's in milliseconds)",
+                    name, rate, MIN_POLL_RATE
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `HAOS_URL` if it's set, otherwise `url` from the file.
+    pub fn url(&self) -> Result<String, String> {
+        match env::var(URL_ENV) {
+            Ok(url) if !url.is_empty() => {
+                check_url(&url).map_err(|e| format!("{} {}", URL_ENV, e))?;
+                Ok(url)
+            }
+            _ => self
+                .url
+                .clone()
+                .ok_or_else(|| format!("No url given, set `url` in the config or {}", URL_ENV)),
+        }
+    }
+
+    /// Works out the token. `HAOS_TOKEN` wins if it's set, otherwise exactly one of `token`,
+    /// `token_file` or `token_command` has to be in the config.
+    pub fn token(&self) -> Result<String, String> {
+        if let Ok(token) = env::var(TOKEN_ENV) {
+            if !token.is_empty() {
+                info!("Using the token from {}", TOKEN_ENV);
+                return Ok(token);
+            }
+        }
+
+        match (&self.token, &self.token_file, &self.token_command) {
+            (Some(token), None, None) => Ok(token.clone()),
+            (None, Some(path), None) => {
+                let path = expand_home(path);
+                info!("Reading the token from {}", path.display());
+                let token = fs::read_to_string(&path)
+                    .map_err(|e| format!("Couldn't read token_file {}: {}", path.display(), e))?;
+                non_empty(token.trim(), "token_file")
+            }
+            (None, None, Some(command)) => {
+                info!("Getting the token from token_command");
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .output()
+                    .map_err(|e| format!("Couldn't run token_command: {}", e))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "token_command exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                non_empty(String::from_utf8_lossy(&output.stdout).trim(), "token_command")
+            }
+            (None, None, None) => Err(format!(
+                "No token given, set one of `token`, `token_file` or `token_command` in the config or {}",
+                TOKEN_ENV
+            )),
+            _ => Err(String::from(
+                "Only one of `token`, `token_file` and `token_command` can be set",
+            )),
+        }
+    }
+
+    /// `log_file` with `~/` expanded, or the default under the XDG state dir.
+    pub fn log_file(&self) -> PathBuf {
+        match &self.log_file {
+            Some(path) => expand_home(path),
+            None => crate::logging::default_log_file(),
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    pub fn cache_file(&self) -> PathBuf {
+        match &self.cache_file {
+            Some(path) => expand_home(path),
+            None => crate::cache::default_cache_file(),
+        }
+    }
+
+    /// Whether the token is sitting in the config file itself, rather than somewhere else.
+    pub fn has_literal_token(&self) -> bool {
+        self.token.is_some()
+    }
+}
+
+/// The url has to be http(s) with a host, IE: `http://homeassistant.local:8123`. The error is
+/// meant to follow the name of whatever held the url.
+pub fn check_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("{} isn't a valid url: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("{} has to start with http:// or https://", url));
+    }
+    if parsed.host_str().is_none() {
+        return Err(format!("{} doesn't have a host", url));
+    }
+    if parsed.path().trim_end_matches('/').ends_with("/api") {
+        return Err(format!("{} should be the address without /api on the end", url));
+    }
+    Ok(())
+}
+
+fn non_empty(token: &str, source: &str) -> Result<String, String> {
+    match token.is_empty() {
+        true => Err(format!("{} gave back an empty token", source)),
+        false => Ok(token.to_string()),
+    }
+}
+
+/// Lets `token_file` & `log_file` start with `~/` like it would in a shell.
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var("HOME")) {
+        (Ok(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Anyone on the box can read the config file, which matters when there's a token in it.
+#[cfg(unix)]
+pub fn is_world_readable(config_path: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(config_path)
+        .map(|meta| meta.permissions().mode() & 0o004 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_world_readable(_config_path: &str) -> bool {
+    false
+}
+
+fn read_toml(config_path: &str) -> Result<Config, String> {
+    let config_string = match fs::read_to_string(config_path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "There's no config at {}, run `haoscli init` to make one",
+                config_path
+            ))
+        }
+        Err(e) => return Err(format!("Couldn't read {}: {}", config_path, e)),
+    };
+    toml::from_str(&config_string).map_err(|e| format!("{}: {}", config_path, e))
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub config_path: String,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        let home_dir = env::var("HOME")
+            .expect("User hasn't defined their HOME variable. Supply a path manually");
+        let env_config_path = format!("{}/.config/haos-rs-client/config.toml", home_dir);
+        info!("{}", env_config_path);
+        Args {
+            config_path: env_config_path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
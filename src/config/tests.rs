@@ -0,0 +1,183 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{Config, TOKEN_ENV, URL_ENV};
+
+/// The environment is shared by every test in the binary, so the ones that touch it take turns.
+static ENV: Mutex<()> = Mutex::new(());
+
+fn lock_env() -> MutexGuard<'static, ()> {
+    let guard = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    env::remove_var(URL_ENV);
+    env::remove_var(TOKEN_ENV);
+    guard
+}
+
+fn config(toml: &str) -> Config {
+    toml::from_str(toml).unwrap()
+}
+
+/// A fresh directory for a test to write into.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("haoscli-config-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn the_environment_beats_the_config() {
+    let _env = lock_env();
+    let config = config("url = \"http://ha.lan:8123\"\ntoken = \"from-the-file\"");
+    assert_eq!(config.token().unwrap(), "from-the-file");
+    assert_eq!(config.url().unwrap(), "http://ha.lan:8123");
+
+    env::set_var(TOKEN_ENV, "from-the-env");
+    env::set_var(URL_ENV, "https://ha.example.com");
+    assert_eq!(config.token().unwrap(), "from-the-env");
+    assert_eq!(config.url().unwrap(), "https://ha.example.com");
+
+    // Set but empty counts as not set.
+    env::set_var(TOKEN_ENV, "");
+    env::set_var(URL_ENV, "");
+    assert_eq!(config.token().unwrap(), "from-the-file");
+    assert_eq!(config.url().unwrap(), "http://ha.lan:8123");
+
+    env::set_var(URL_ENV, "ha.lan");
+    assert!(config.url().unwrap_err().starts_with("HAOS_URL ha.lan isn't a valid url"));
+    env::remove_var(URL_ENV);
+    env::remove_var(TOKEN_ENV);
+}
+
+#[test]
+fn the_environment_beats_a_broken_config() {
+    let _env = lock_env();
+    let config = config("token = \"a\"\ntoken_command = \"exit 1\"");
+    env::set_var(TOKEN_ENV, "from-the-env");
+    assert_eq!(config.token().unwrap(), "from-the-env");
+    env::remove_var(TOKEN_ENV);
+    assert!(config.token().unwrap_err().starts_with("Only one of"));
+    assert!(config.url().unwrap_err().starts_with("No url given"));
+}
+
+#[test]
+fn token_file() {
+    let _env = lock_env();
+    let dir = scratch_dir("token-file");
+    std::fs::write(dir.join("token"), "  from-a-file\n").unwrap();
+    std::fs::write(dir.join("empty"), "\n").unwrap();
+    let from = |name: &str| config(&format!("token_file = {:?}", dir.join(name)));
+
+    assert_eq!(from("token").token().unwrap(), "from-a-file");
+    assert_eq!(from("empty").token().unwrap_err(), "token_file gave back an empty token");
+    assert!(from("missing").token().unwrap_err().starts_with("Couldn't read token_file"));
+}
+
+#[test]
+fn token_command() {
+    let _env = lock_env();
+    let from = |command: &str| config(&format!("token_command = {:?}", command)).token();
+
+    assert_eq!(from("echo from-a-command").unwrap(), "from-a-command");
+    assert_eq!(from("true").unwrap_err(), "token_command gave back an empty token");
+    let failed = from("echo locked >&2; exit 3").unwrap_err();
+    assert!(failed.starts_with("token_command exited with"), "{}", failed);
+    assert!(failed.ends_with(": locked"), "{}", failed);
+}
+
+#[test]
+fn exactly_one_token_source() {
+    let _env = lock_env();
+    assert!(config("").token().unwrap_err().starts_with("No token given"));
+    for both in [
+        "token = \"a\"\ntoken_file = \"/a\"",
+        "token_file = \"/a\"\ntoken_command = \"echo a\"",
+        "token = \"a\"\ntoken_file = \"/a\"\ntoken_command = \"echo a\"",
+    ] {
+        assert!(config(both).token().unwrap_err().starts_with("Only one of"), "{:?}", both);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn world_readable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = scratch_dir("world-readable").join("config.toml");
+    std::fs::write(&path, "").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    assert!(!super::is_world_readable(path.to_str().unwrap()));
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(super::is_world_readable(path.to_str().unwrap()));
+}
+
+/// Goes through `Config::new` like haoscli does, so the errors are the ones a user sees.
+fn load(test: &str, toml: &str) -> Result<Config, String> {
+    let path = scratch_dir(test).join("config.toml");
+    std::fs::write(&path, toml).unwrap();
+    Config::new(super::Args {
+        config_path: path.to_str().unwrap().to_string(),
+    })
+}
+
+#[test]
+fn everything_but_the_url_has_a_default() {
+    let config = load("defaults", "url = \"http://homeassistant.local:8123\"").unwrap();
+    assert_eq!(config.client_id, "haoscli");
+    assert!(matches!(config.log_level, super::LogLevel::Warn));
+    assert_eq!(config.poll_rate, 1000);
+    assert_eq!(config.log_max_size, 1024);
+    assert_eq!(config.queue_max_age, 300);
+    assert!(!config.queue_offline);
+    assert!(load("empty", "").is_ok());
+}
+
+#[test]
+fn bad_urls() {
+    for (url, error) in [
+        ("homeassistant.local", "isn't a valid url"),
+        ("homeassistant.local:8123", "has to start with http:// or https://"),
+        ("ftp://homeassistant.local", "has to start with http:// or https://"),
+        ("http://homeassistant.local:8123/api", "should be the address without /api on the end"),
+        ("http://homeassistant.local:8123/api/", "should be the address without /api on the end"),
+    ] {
+        let e = load("bad-url", &format!("url = {:?}", url)).err().unwrap();
+        assert!(e.contains(&format!(": url {} {}", url, error)), "{}", e);
+    }
+    assert!(super::check_url("https://ha.example.com/prefix/").is_ok());
+}
+
+#[test]
+fn poll_rates_too_low() {
+    let e = load("poll-rate", "poll_rate = 99").err().unwrap();
+    assert!(e.ends_with("poll_rate is 99, it has to be at least 100 (it's in milliseconds)"), "{}", e);
+    assert!(load("poll-rate", "poll_rate = 100").is_ok());
+
+    let e = load("refresh", "[refresh]\nstates = 5000\nenergy = 10").err().unwrap();
+    assert!(e.ends_with("refresh.energy is 10, it has to be at least 100 (it's in milliseconds)"), "{}", e);
+}
+
+#[test]
+fn unknown_keys_and_values() {
+    assert!(load("log-level", "log_level = \"info\"").is_ok());
+    assert!(load("log-level", "log_level = \"Info\"").is_ok());
+    let e = load("log-level", "log_level = \"loud\"").err().unwrap();
+    assert!(e.contains("unknown variant `loud`"), "{}", e);
+
+    let e = load("unknown-key", "pol_rate = 1000").err().unwrap();
+    assert!(e.contains("unknown field `pol_rate`"), "{}", e);
+    let e = load("unknown-refresh", "[refresh]\nlights = 1000").err().unwrap();
+    assert!(e.contains("unknown field `lights`"), "{}", e);
+}
+
+#[test]
+fn missing_config() {
+    let path = scratch_dir("missing").join("config.toml");
+    let e = Config::new(super::Args {
+        config_path: path.to_str().unwrap().to_string(),
+    })
+    .err()
+    .unwrap();
+    assert!(e.ends_with("run `haoscli init` to make one"), "{}", e);
+}
@@ -0,0 +1,95 @@
+//! Finding Home Assistant instances on the LAN. They announce themselves over mDNS as
+//! `_home-assistant._tcp.local.` with their name, version & url in the TXT record.
+use std::net::IpAddr;
+use std::time::Duration;
+
+use log::{debug, info};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+const SERVICE_TYPE: &str = "_home-assistant._tcp.local.";
+
+/// An instance that answered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredInstance {
+    /// What it's called in Home Assistant, IE: "Home".
+    pub name: String,
+    pub version: Option<String>,
+    /// The address to point haoscli at, IE: `http://192.168.1.20:8123`.
+    pub base_url: String,
+    pub uuid: Option<String>,
+}
+
+/// Listens for instances for `timeout` then hands back everything that answered, without
+/// duplicates. An empty list just means nothing answered in time.
+pub async fn discover(timeout: Duration) -> Result<Vec<DiscoveredInstance>> {
+    let daemon = ServiceDaemon::new().map_err(|e| Error::Discovery(e.to_string()))?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| Error::Discovery(e.to_string()))?;
+    info!("Browsing for {} for {:?}", SERVICE_TYPE, timeout);
+
+    let mut found: Vec<DiscoveredInstance> = Vec::new();
+    let deadline = tokio::time::Instant::now() + timeout;
+    while let Ok(event) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+        match event {
+            Ok(ServiceEvent::ServiceResolved(service)) => {
+                let instance = to_instance(&service);
+                debug!("Found {:?}", instance);
+                // Instances on more than one interface answer more than once.
+                if !found.iter().any(|known| known.base_url == instance.base_url) {
+                    found.push(instance);
+                }
+            }
+            Ok(other) => debug!("mdns: {:?}", other),
+            Err(_) => break,
+        }
+    }
+
+    // The daemon runs on its own thread, nothing to wait for on the way out.
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+fn to_instance(service: &ServiceInfo) -> DiscoveredInstance {
+    let txt = |key: &str| {
+        service
+            .get_property_val_str(key)
+            .filter(|value| !value.is_empty())
+            .map(String::from)
+    };
+    let name = txt("location_name").unwrap_or_else(|| {
+        service
+            .get_fullname()
+            .trim_end_matches(SERVICE_TYPE)
+            .trim_end_matches('.')
+            .to_string()
+    });
+    // Newer versions send internal_url, older ones base_url. Failing both, the address it answered
+    // from will do.
+    let base_url = txt("internal_url")
+        .or_else(|| txt("base_url"))
+        .unwrap_or_else(|| {
+            let mut addresses: Vec<&IpAddr> = service.get_addresses().iter().collect();
+            // IPv4 first, they're the ones people recognise.
+            addresses.sort_by_key(|address| address.is_ipv6());
+            let host = match addresses.first() {
+                Some(IpAddr::V4(v4)) => v4.to_string(),
+                Some(IpAddr::V6(v6)) => format!("[{}]", v6),
+                None => service.get_hostname().trim_end_matches('.').to_string(),
+            };
+            format!("http://{}:{}", host, service.get_port())
+        });
+
+    DiscoveredInstance {
+        name,
+        version: txt("version"),
+        base_url: base_url.trim_end_matches('/').to_string(),
+        uuid: txt("uuid"),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,49 @@
+use mdns_sd::ServiceInfo;
+
+use super::{to_instance, DiscoveredInstance, SERVICE_TYPE};
+
+/// What an instance called "Home" announces, with `addresses` comma separated.
+fn announced(addresses: &str, txt: &[(&str, &str)]) -> ServiceInfo {
+    ServiceInfo::new(SERVICE_TYPE, "Home", "homeassistant.local.", addresses, 8123, txt).unwrap()
+}
+
+#[test]
+fn everything_from_the_txt_record() {
+    let service = announced(
+        "192.168.1.20",
+        &[
+            ("location_name", "Our House"),
+            ("version", "2024.1.0"),
+            ("internal_url", "http://homeassistant.lan:8123/"),
+            ("base_url", "http://old.lan:8123"),
+            ("uuid", "0123456789abcdef"),
+        ],
+    );
+    assert_eq!(
+        to_instance(&service),
+        DiscoveredInstance {
+            name: String::from("Our House"),
+            version: Some(String::from("2024.1.0")),
+            base_url: String::from("http://homeassistant.lan:8123"),
+            uuid: Some(String::from("0123456789abcdef")),
+        }
+    );
+}
+
+#[test]
+fn older_versions_send_base_url() {
+    let service = announced("192.168.1.20", &[("internal_url", ""), ("base_url", "http://old.lan:8123")]);
+    assert_eq!(to_instance(&service).base_url, "http://old.lan:8123");
+}
+
+#[test]
+fn without_a_url_the_address_will_do() {
+    let instance = to_instance(&announced("fe80::1,192.168.1.20", &[("location_name", "")]));
+    assert_eq!(instance.base_url, "http://192.168.1.20:8123");
+    // An empty location_name is as good as none, the name comes out of the service's.
+    assert_eq!(instance.name, "Home");
+    assert_eq!((instance.version, instance.uuid), (None, None));
+
+    assert_eq!(to_instance(&announced("fe80::1", &[])).base_url, "http://[fe80::1]:8123");
+    assert_eq!(to_instance(&announced("", &[])).base_url, "http://homeassistant.local:8123");
+}
@@ -0,0 +1,137 @@
+//! Boils the energy dashboard's long-term statistics down to what the energy pane draws.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+
+use haoscli::error::Result;
+use haoscli::types::{EnergyPreferences, StatisticValue, StatisticsPeriod};
+use haoscli::websocket::HomeAssistantWebSocket;
+
+use crate::ui_types::EnergySummary;
+
+/// How far back each period looks, picked so the bars still fit on a normal sized terminal.
+fn window_start(period: StatisticsPeriod) -> DateTime<Utc> {
+    let span = match period {
+        StatisticsPeriod::FiveMinute => Duration::hours(2),
+        StatisticsPeriod::Hour => Duration::hours(24),
+        StatisticsPeriod::Day => Duration::days(14),
+        StatisticsPeriod::Week => Duration::weeks(12),
+        StatisticsPeriod::Month => Duration::days(365),
+    };
+    Utc::now() - span
+}
+
+/// How much each bucket went up by. Older versions of Home Assistant don't hand back `change`, in
+/// which case it's worked out from the running `sum` (and the first bucket is lost).
+fn changes(values: &[StatisticValue]) -> Vec<(DateTime<Utc>, f64)> {
+    if values.iter().any(|value| value.change.is_some()) {
+        return values
+            .iter()
+            .map(|value| (value.start, value.change.unwrap_or_default()))
+            .collect();
+    }
+    values
+        .windows(2)
+        .filter_map(|pair| match (pair[0].sum, pair[1].sum) {
+            (Some(before), Some(after)) => Some((pair[1].start, after - before)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The statistics the energy dashboard is made of, by what they are on it.
+#[derive(Debug, Default, PartialEq)]
+struct EnergyIds {
+    import: Vec<String>,
+    export: Vec<String>,
+    solar: Vec<String>,
+    devices: Vec<String>,
+}
+
+impl EnergyIds {
+    fn from_prefs(prefs: &EnergyPreferences) -> Self {
+        let mut ids = EnergyIds::default();
+        for source in &prefs.energy_sources {
+            match source.kind.as_str() {
+                "grid" => {
+                    ids.import.extend(source.flow_from.iter().filter_map(|flow| flow.stat_energy_from.clone()));
+                    ids.export.extend(source.flow_to.iter().filter_map(|flow| flow.stat_energy_to.clone()));
+                }
+                "solar" => ids.solar.extend(source.stat_energy_from.clone()),
+                _ => (),
+            }
+        }
+        ids.devices = prefs
+            .device_consumption
+            .iter()
+            .map(|device| device.stat_consumption.clone())
+            .collect();
+        ids
+    }
+
+    fn all(&self) -> Vec<String> {
+        [&self.import, &self.export, &self.solar, &self.devices]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Grabs the energy dashboard config and its statistics over the window for `period`. `names` maps
+/// entity ids to friendly names for labelling the devices.
+pub async fn fetch_energy_summary(
+    websocket: &mut HomeAssistantWebSocket,
+    period: StatisticsPeriod,
+    names: &HashMap<String, String>,
+) -> Result<EnergySummary> {
+    let ids = EnergyIds::from_prefs(&websocket.get_energy_prefs().await?);
+    let all_ids = ids.all();
+    if all_ids.is_empty() {
+        return Ok(EnergySummary::default());
+    }
+    let stats = websocket
+        .statistics_during_period(&all_ids, window_start(period), None, period)
+        .await?;
+    Ok(summarize(&ids, &stats, names))
+}
+
+/// Adds the statistics up into the grid import bars & the totals for each source and device.
+fn summarize(
+    ids: &EnergyIds,
+    stats: &HashMap<String, Vec<StatisticValue>>,
+    names: &HashMap<String, String>,
+) -> EnergySummary {
+    let changes_for = |id: &String| stats.get(id).map(|values| changes(values)).unwrap_or_default();
+    let total = |ids: &[String]| -> f64 {
+        ids.iter()
+            .flat_map(&changes_for)
+            .map(|(_, change)| change)
+            .sum()
+    };
+
+    let mut grid_import: BTreeMap<DateTime<Utc>, f64> = BTreeMap::new();
+    for (start, change) in ids.import.iter().flat_map(&changes_for) {
+        *grid_import.entry(start).or_default() += change;
+    }
+
+    let devices = ids
+        .devices
+        .iter()
+        .map(|id| {
+            let name = names.get(id).cloned().unwrap_or_else(|| id.clone());
+            (name, total(std::slice::from_ref(id)))
+        })
+        .collect();
+
+    EnergySummary {
+        grid_import: grid_import.into_iter().collect(),
+        grid_import_total: total(&ids.import),
+        grid_export_total: total(&ids.export),
+        solar_total: total(&ids.solar),
+        devices,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::json;
+
+use haoscli::types::{EnergyPreferences, StatisticValue};
+
+use super::{changes, summarize, EnergyIds};
+
+fn hour(h: u32) -> DateTime<Utc> {
+    Utc.ymd(2024, 1, 1).and_hms(h, 0, 0)
+}
+
+fn bucket(h: u32, change: Option<f64>, sum: Option<f64>) -> StatisticValue {
+    StatisticValue {
+        start: hour(h),
+        change,
+        sum,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn changes_are_used_as_is() {
+    let values = [bucket(0, Some(1.5), Some(10.0)), bucket(1, None, Some(12.0)), bucket(2, Some(0.25), None)];
+    assert_eq!(changes(&values), vec![(hour(0), 1.5), (hour(1), 0.0), (hour(2), 0.25)]);
+}
+
+#[test]
+fn changes_are_worked_out_from_the_sum() {
+    let values = [bucket(0, None, Some(10.0)), bucket(1, None, Some(12.5)), bucket(2, None, Some(13.0))];
+    assert_eq!(changes(&values), vec![(hour(1), 2.5), (hour(2), 0.5)]);
+}
+
+#[test]
+fn buckets_missing_a_sum_are_skipped() {
+    let values = [bucket(0, None, Some(10.0)), bucket(1, None, None), bucket(2, None, Some(13.0)), bucket(3, None, Some(14.0))];
+    assert_eq!(changes(&values), vec![(hour(3), 1.0)]);
+    assert_eq!(changes(&[bucket(0, None, Some(10.0))]), vec![]);
+    assert_eq!(changes(&[]), vec![]);
+}
+
+fn prefs() -> EnergyPreferences {
+    serde_json::from_value(json!({
+        "energy_sources": [
+            {
+                "type": "grid",
+                "flow_from": [{"stat_energy_from": "sensor.peak"}, {"stat_energy_from": "sensor.off_peak"}],
+                "flow_to": [{"stat_energy_to": "sensor.feed_in"}],
+            },
+            {"type": "solar", "stat_energy_from": "sensor.panels"},
+            {"type": "gas", "stat_energy_from": "sensor.gas"},
+        ],
+        "device_consumption": [{"stat_consumption": "sensor.kettle"}, {"stat_consumption": "sensor.fridge"}],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn ids_by_what_they_are_on_the_dashboard() {
+    let ids = EnergyIds::from_prefs(&prefs());
+    assert_eq!(ids.import, ["sensor.peak", "sensor.off_peak"]);
+    assert_eq!(ids.export, ["sensor.feed_in"]);
+    assert_eq!(ids.solar, ["sensor.panels"]);
+    assert_eq!(ids.devices, ["sensor.kettle", "sensor.fridge"]);
+    assert_eq!(ids.all().len(), 6);
+    assert!(EnergyIds::from_prefs(&EnergyPreferences::default()).all().is_empty());
+}
+
+#[test]
+fn summed_up_by_source_and_device() {
+    // 2024-01-01T00:00:00Z & an hour later in milliseconds, as newer versions send them.
+    let (midnight, one) = (1_704_067_200_000u64, 1_704_070_800_000u64);
+    let stats: HashMap<String, Vec<StatisticValue>> = serde_json::from_value(json!({
+        "sensor.peak": [
+            {"start": midnight, "end": one, "change": 1.0},
+            {"start": one, "end": null, "change": 2.0},
+        ],
+        "sensor.off_peak": [
+            {"start": "2024-01-01T00:00:00+00:00", "end": "2024-01-01T01:00:00+00:00", "change": 0.5},
+        ],
+        "sensor.feed_in": [
+            {"start": midnight, "sum": 100.0},
+            {"start": one, "sum": 103.0},
+        ],
+        "sensor.panels": [{"start": midnight, "change": 4.0}],
+        "sensor.kettle": [{"start": midnight, "change": 0.25}, {"start": one, "change": 0.5}],
+    }))
+    .unwrap();
+    let names = HashMap::from([(String::from("sensor.kettle"), String::from("Kettle"))]);
+
+    let summary = summarize(&EnergyIds::from_prefs(&prefs()), &stats, &names);
+    assert_eq!(summary.grid_import, vec![(hour(0), 1.5), (hour(1), 2.0)]);
+    assert_eq!(summary.grid_import_total, 3.5);
+    assert_eq!(summary.grid_export_total, 3.0);
+    assert_eq!(summary.solar_total, 4.0);
+    // The fridge has no statistics, it's still listed under its entity id.
+    assert_eq!(
+        summary.devices,
+        vec![(String::from("Kettle"), 0.75), (String::from("sensor.fridge"), 0.0)]
+    );
+}
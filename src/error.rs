@@ -0,0 +1,79 @@
+use std::fmt;
+
+/// Shorthand for results coming back from the Home Assistant instance.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong when talking to the Home Assistant instance.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request never made it or the body couldn't be read/parsed.
+    Request(reqwest::Error),
+    /// The server answered, but not with a success code. Holds the body it sent back.
+    Status(reqwest::StatusCode, String),
+    /// The Supervisor answered with `"result": "error"`. Holds its message.
+    Supervisor(String),
+    /// The WebSocket connection failed, or Home Assistant answered a command with an error.
+    WebSocket(String),
+    /// The connection was set up with something that can't work, IE: an unparsable CA bundle.
+    Config(String),
+    /// Browsing for instances over mDNS couldn't get going, IE: no network interfaces.
+    Discovery(String),
+    /// A condition, IE: `attributes.brightness>100`, couldn't be parsed.
+    Condition(String),
+    /// A cassette couldn't be read, or the request being replayed isn't on it.
+    Cassette(String),
+    /// Reading or writing a local file failed, IE: while saving a downloaded backup.
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Whether Home Assistant couldn't be reached at all, IE: it's restarting, as opposed to it
+    /// answering with an error. A proxy in front of it answers 502-504 while it's gone.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            Error::Request(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            Error::Status(code, _) => matches!(code.as_u16(), 502..=504),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Request(e) => write!(f, "request failed: {}", e),
+            Error::Status(code, body) if body.is_empty() => write!(f, "HTTP {}", code),
+            Error::Status(code, body) => write!(f, "HTTP {}: {}", code, body),
+            Error::Supervisor(msg) => write!(f, "supervisor error: {}", msg),
+            Error::WebSocket(msg) => write!(f, "websocket error: {}", msg),
+            Error::Config(msg) => write!(f, "bad configuration: {}", msg),
+            Error::Discovery(msg) => write!(f, "discovery failed: {}", msg),
+            Error::Condition(msg) => write!(f, "bad condition: {}", msg),
+            Error::Cassette(msg) => write!(f, "cassette error: {}", msg),
+            Error::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Request(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -1,119 +1,268 @@
-use haoscli::types::{Event, HomeAssistantConnection, Service, State, RequestEntityObject, RequestStateStruct, RequestServiceStruct};
+use haoscli::types::{
+    HomeAssistantConnection, NewBackup, RequestEntityObject, RequestServiceStruct, RequestStateStruct, State,
+};
 
 use std::{
-    io::Error,
-    sync::{Arc, Condvar, Mutex, RwLock},
-    thread,
+    sync::{mpsc::Sender, Arc, RwLock},
     time::Duration,
 };
 
-use log::{info, trace, warn, debug};
+use haoscli::error::Result;
+use haoscli::websocket::HomeAssistantWebSocket;
+
+use log::{debug, info, trace, warn};
+use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
-use crate::ui_types::{Pane, UiState, PopUpPane};
+use crate::actions::{Action, Applied, Change, Command, Response};
+use crate::energy::fetch_energy_summary;
+use crate::ui_types::{backup_file, BackupAction};
 
+/// How long the changes still being sent get to finish once the UI has closed.
+const CLOSING_GRACE: Duration = Duration::from_secs(10);
 
-#[allow(clippy::await_holding_lock)]
+/// Carries out the reducer's commands, each as its own task so a slow backup doesn't hold up the
+/// polls, and sends back what came of them.
+///
+/// Stops as soon as `shutdown` is cancelled or the UI hangs up. Polls still going are dropped, the
+/// changes the user asked for get `CLOSING_GRACE` to finish, or until `give_up` is cancelled. Gives
+/// back how many didn't.
 pub async fn fetcher(
-    haos_conn_locked: &Arc<RwLock<HomeAssistantConnection>>,
-    convar: &Arc<Condvar>,
-    state: &mut Arc<Mutex<UiState>>,
-    poll_rate: u64,
-) {
-    //let events = match rt.block_on(working_haos_conn.get_events()) {Ok(v) => v, Err(_) => panic!("Couldn't access the resouce")};
+    haos_conn: Arc<RwLock<HomeAssistantConnection>>,
+    mut commands: UnboundedReceiver<Command>,
+    actions: Sender<Action>,
+    shutdown: CancellationToken,
+    give_up: CancellationToken,
+) -> usize {
+    // Opened the first time the energy pane is looked at and kept around after that.
+    let websocket: Arc<Mutex<Option<HomeAssistantWebSocket>>> = Arc::new(Mutex::new(None));
+    let mut polls: Vec<JoinHandle<()>> = Vec::new();
+    // The changes the user asked for, which get to finish even if the UI is closed right after.
+    let mut changes: Vec<JoinHandle<()>> = Vec::new();
+
     loop {
-        {
-            let ui_state = match state.try_lock() {
-                Ok(v) => v,
-                Err(e) => {
-                    warn!("Error getting UI lock in the fetcher: {}", e);
-                    thread::sleep(Duration::from_millis(250));
-                    continue;
+        let command = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            command = commands.recv() => match command {
+                Some(command) => command,
+                None => break,
+            },
+        };
+        debug!("Fetcher got {:?}", command);
+        let is_change = matches!(command, Command::Send(..) | Command::Replay(_));
+        let haos_conn = Arc::clone(&haos_conn);
+        let websocket = Arc::clone(&websocket);
+        let actions = actions.clone();
+        let task = tokio::spawn(async move {
+            run_command(&haos_conn, &websocket, command, &actions).await;
+        });
+        polls.retain(|poll| !poll.is_finished());
+        changes.retain(|change| !change.is_finished());
+        match is_change {
+            true => changes.push(task),
+            false => polls.push(task),
+        }
+    }
+
+    for poll in polls {
+        poll.abort();
+    }
+    changes.retain(|change| !change.is_finished());
+    if changes.is_empty() {
+        return 0;
+    }
+    info!("Waiting on {} changes before closing", changes.len());
+    eprintln!("Waiting on {} changes to finish, Ctrl+c to stop waiting...", changes.len());
+    let waiting = changes.len();
+    let finished = tokio::select! {
+        finished = tokio::time::timeout(CLOSING_GRACE, async {
+            for change in &mut changes {
+                let _ = change.await;
+            }
+        }) => finished.is_ok(),
+        _ = give_up.cancelled() => false,
+    };
+    if !finished {
+        let unfinished = changes.iter().filter(|change| !change.is_finished()).count();
+        warn!("Gave up waiting on {} of {} changes", unfinished, waiting);
+        return unfinished;
+    }
+    0
+}
+
+async fn run_command(
+    haos_conn_locked: &RwLock<HomeAssistantConnection>,
+    websocket: &Mutex<Option<HomeAssistantWebSocket>>,
+    command: Command,
+    actions: &Sender<Action>,
+) {
+    // A copy of its own, so the lock isn't held while the request waits.
+    let haos_conn = haos_conn_locked.read().expect("Couldn't get the read lock").clone();
+    let response = match command {
+        Command::FetchEvents => {
+            let events = haos_conn.get_events().await;
+            info!("recived response for event update from HAOS");
+            if let Ok(event) = &events {
+                trace!("Event Recieved: {:?}", event);
+            }
+            Response::Events(events)
+        }
+        Command::FetchServices => {
+            let services = haos_conn.get_services().await;
+            info!("Recieved a response for Services from HAOS");
+            if let Ok(service) = &services {
+                trace!("Service recieved: {:?}", service);
+            }
+            Response::Services(services)
+        }
+        Command::FetchStates => {
+            let states = haos_conn.get_states().await;
+            info!("Recieved a respsone for states from Haos");
+            Response::States(states)
+        }
+        Command::FetchAddons => Response::Addons(haos_conn.get_addons().await),
+        Command::FetchAddonLogs(slug) => {
+            let logs = haos_conn.get_addon_logs(&slug).await.map(|logs| strip_ansi(&logs));
+            Response::AddonLogs(slug, logs)
+        }
+        Command::FetchBackups => Response::Backups(haos_conn.get_backups().await),
+        Command::FetchEnergy(period, names) => {
+            let mut websocket = websocket.lock().await;
+            if websocket.is_none() {
+                match haos_conn.connect_websocket().await {
+                    Ok(v) => *websocket = Some(v),
+                    Err(e) => warn!("Couldn't open the websocket: {}", e),
                 }
+            }
+            let summary = match websocket.as_mut() {
+                Some(ws) => fetch_energy_summary(ws, period, &names).await,
+                None => Err(haoscli::error::Error::WebSocket(String::from("not connected"))),
             };
-            if ui_state.active == Pane::None {
-                break;
+            // Opened again next time, it's probably what broke.
+            if summary.is_err() {
+                *websocket = None;
             }
-            drop(ui_state);
+            Response::Energy(period, summary)
         }
-
-        let mut state_lock = state.lock().expect("Could not get the lock on the state");
-
-        // Going to check that we have marked "input as ready" as ready. 
-        //let state_update: Result<State, Error>;
-        if state_lock.input_pane.1 {
-            let haos_conn = haos_conn_locked.read().expect("Couldn't get the read lock");
-            match state_lock.active {
-                Pane::PopUp(PopUpPane::States) => {
-                    let selected_state = state_lock.states.0.get(state_lock.states.1.selected().expect("Couldn't get the selected value")).expect("Couldn't select the selected state from the list of states");
-                    let set_state = RequestStateStruct{state: serde_json::from_str(state_lock.input_pane.0.as_str()).expect("Couldn't parse the text as a json") };
-                    _ = haos_conn.set_state(selected_state, set_state).await.expect("Couldn't send the data to the end point");
-                },
-                Pane::PopUp(PopUpPane::Services) => {
-                    let selected_service = state_lock.services.0.get(state_lock.services.1.selected().expect("Couldn't get the selected value")).expect("Couldn't get the selected state in the UI from the UIState object");
-                    let entity_to_set = RequestEntityObject{entity_id: state_lock.input_pane.0.as_str()};
-                    let service_to_send = RequestServiceStruct{domain: selected_service.domain.as_str(), service: state_lock.services_popup_selected.as_str()};
-                    debug!("entity_to_set:\t{:?}, service_to_send:\t{:?}", entity_to_set, service_to_send);
-
-                    _ = haos_conn.set_service(&service_to_send, Some(&entity_to_set)).await.expect("couldn't send the data to the end point");
-                },
-                Pane::PopUp(PopUpPane::Events) => {
-                    todo!("Currently don't support sending an event, sorry")
-                },
-                _ => (),
+        Command::Send(id, change) => Response::Sent(id, run_change(&haos_conn, change).await),
+        // One at a time, so they land in the order they were made.
+        Command::Replay(changes) => {
+            for (id, change) in changes {
+                let result = run_change(&haos_conn, change).await;
+                let _ = actions.send(Action::Response(Response::Sent(id, result)));
             }
-
-            // Getting the seleted state
-            state_lock.input_pane = (String::from(""), false);
+            return;
         }
+        Command::ResetWebSocket => {
+            info!("Closing the websocket, it's opened again when it's next needed");
+            *websocket.lock().await = None;
+            return;
+        }
+    };
+    // Nobody's listening once the UI has closed, which is fine.
+    let _ = actions.send(Action::Response(response));
+}
 
-        drop(state_lock);
-
-        let mut state_lock = state.lock().expect("Could not get the lock on the state");
-        let events: Result<Vec<Event>, Error>;
-        {
-            let haos_conn = haos_conn_locked.read().expect("Couldn't get the read lock");
-            let temp_events = haos_conn.get_events();
-            info!("recived response for event update from HAOS");
-            events = Ok(temp_events.await.unwrap());
+/// Makes the change & describes what Home Assistant said about it, IE: "light.desk is now off".
+async fn run_change(haos_conn: &HomeAssistantConnection, change: Change) -> Result<Applied> {
+    match change {
+        Change::Addon(slug, action) => {
+            haos_conn.addon_action(&slug, action).await?;
+            Ok(Applied {
+                summary: format!("{:?} {}: ok", action, slug),
+                changed: Vec::new(),
+            })
+        }
+        Change::Backup(action) => Ok(Applied {
+            summary: run_backup_action(haos_conn, action).await?,
+            changed: Vec::new(),
+        }),
+        Change::SetState(selected_state, payload) => {
+            let set_state = RequestStateStruct { state: payload };
+            let state = haos_conn.set_state(&selected_state, set_state).await?;
+            Ok(Applied {
+                summary: format!("{} is now {}", state.entity_id, state.state),
+                changed: vec![state],
+            })
         }
-        for event in &events {
-            trace!("Event Recieved: {:?}", event);
+        Change::CallService { domain, service, entity_id } => {
+            let entity_to_set = RequestEntityObject { entity_id: entity_id.as_str() };
+            let service_to_send = RequestServiceStruct { domain: domain.as_str(), service: service.as_str() };
+            debug!("entity_to_set:\t{:?}, service_to_send:\t{:?}", entity_to_set, service_to_send);
+            let changed = haos_conn.set_service(&service_to_send, Some(&entity_to_set)).await?;
+            Ok(describe_changed(&format!("{}.{}", domain, service), changed))
         }
-        state_lock.events.0 = events.expect("test");
+    }
+}
 
+/// Services answer with the states they changed, which makes for a better toast than "ok".
+fn describe_changed(service: &str, changed: serde_json::Value) -> Applied {
+    let changed: Vec<State> = match changed {
+        serde_json::Value::Array(states) => states
+            .into_iter()
+            .filter_map(|state| serde_json::from_value(state).ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let summary = match changed.is_empty() {
+        true => format!("{} done, nothing changed", service),
+        false => changed
+            .iter()
+            .map(|state| format!("{} is now {}", state.entity_id, state.state))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    Applied { summary, changed }
+}
 
-        let services: Result<Vec<Service>, Error>;
-        {
-            let haos_conn = haos_conn_locked
-                .read()
-                .expect("Couldn't get the read lock to unlock the service");
-            let temp_services = haos_conn.get_services();
-            info!("Recieved a response for Services from HAOS");
-            services = Ok(temp_services.await.expect("Couldn't get the services"));
+/// Carries out what was asked for in the backups pane and describes how it went.
+async fn run_backup_action(haos_conn: &HomeAssistantConnection, action: BackupAction) -> Result<String> {
+    match &action {
+        BackupAction::Create(name) => {
+            let new_backup = NewBackup {
+                name: (!name.is_empty()).then(|| name.clone()),
+                ..Default::default()
+            };
+            haos_conn
+                .create_backup(&new_backup)
+                .await
+                .map(|slug| format!("created {}", slug))
         }
-
-        for service in &services {
-            trace!("Service recieved: {:?}", service);
+        BackupAction::Delete(slug) => haos_conn
+            .delete_backup(slug)
+            .await
+            .map(|_| format!("deleted {}", slug)),
+        BackupAction::Download(slug) => {
+            let path = backup_file(slug);
+            haos_conn
+                .download_backup(slug, &path)
+                .await
+                .map(|written| format!("saved {} ({} bytes)", path.display(), written))
         }
+    }
+}
 
-        state_lock.services.0 = services.expect("Couldn't unwrap the services value");
-
-        let states: Result<Vec<State>, Error>;
-        {
-            let haos_conn = haos_conn_locked
-                .read()
-                .expect("Couldn't get the read lock to unlock the state");
-            let temp_states = haos_conn.get_states();
-            info!("Recieved a respsone for states from Haos");
-            states = Ok(temp_states.await.expect("Couldn't get the states"));
+/// The supervisor hands back logs with the terminal colour codes still in them, which the UI would
+/// print as garbage. This drops the `ESC [ ... <letter>` sequences.
+fn strip_ansi(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            ret.push(ch);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for code in chars.by_ref() {
+                if code.is_ascii_alphabetic() {
+                    break;
+                }
+            }
         }
-
-        state_lock.states.0 = states.expect("Couldn't unwrap the states value");
-
-        drop(state_lock);
-
-        convar.notify_all();
-
-        thread::sleep(Duration::from_millis(poll_rate));
     }
+    ret
 }
+
+#[cfg(test)]
+mod tests;
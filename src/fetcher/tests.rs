@@ -0,0 +1,25 @@
+use super::strip_ansi;
+
+#[test]
+fn colour_codes_are_dropped() {
+    assert_eq!(
+        strip_ansi("\x1b[32m2024-01-01 10:00:00 INFO\x1b[0m Starting \x1b[1;31mmosquitto\x1b[0m"),
+        "2024-01-01 10:00:00 INFO Starting mosquitto"
+    );
+    assert_eq!(strip_ansi("\x1b[2K\x1b[1Aline"), "line");
+}
+
+#[test]
+fn everything_else_is_left_alone() {
+    assert_eq!(strip_ansi(""), "");
+    assert_eq!(strip_ansi("[32m isn't a code without the escape, ünïcode is kept"), "[32m isn't a code without the escape, ünïcode is kept");
+    assert_eq!(strip_ansi("one\ntwo\r\n"), "one\ntwo\r\n");
+}
+
+#[test]
+fn cut_off_codes() {
+    // A lone escape takes the character after it, one left open runs to the end.
+    assert_eq!(strip_ansi("before\x1bXafter"), "beforeafter");
+    assert_eq!(strip_ansi("before\x1b[32"), "before");
+    assert_eq!(strip_ansi("before\x1b"), "before");
+}
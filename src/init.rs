@@ -0,0 +1,141 @@
+//! `haoscli init`: asks for the url & token, checks they work and writes the config file. Runs
+//! before the config is read since there usually isn't one yet.
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use clap::{arg, ArgMatches, Command};
+
+use haoscli::types::HomeAssistantConnection;
+
+use crate::config::check_url;
+
+/// What a fresh Home Assistant install answers on, offered when there's no config yet.
+pub const DEFAULT_URL: &str = "http://homeassistant.local:8123";
+
+pub fn command<'help>() -> Command<'help> {
+    Command::new("init")
+        .about("Make a config file, asking for the url & token and checking they work")
+        .arg(arg!(--force "Overwrite an existing config without asking"))
+}
+
+pub fn run(rt: &tokio::runtime::Runtime, config_path: &str, matches: &ArgMatches) -> Result<(), String> {
+    let path = Path::new(config_path);
+    if path.exists()
+        && !matches.contains_id("force")
+        && !confirm(&format!("{} already exists, overwrite it?", path.display()))?
+    {
+        return Ok(());
+    }
+
+    let url = loop {
+        let url = prompt(&format!("Home Assistant url [{}]: ", DEFAULT_URL))?;
+        let url = match url.is_empty() {
+            true => String::from(DEFAULT_URL),
+            false => url.trim_end_matches('/').to_string(),
+        };
+        match check_url(&url) {
+            Ok(()) => break url,
+            Err(e) => eprintln!("The url {}", e),
+        }
+    };
+
+    println!("Make a long-lived access token under your profile in Home Assistant (Security tab).");
+    let token = loop {
+        let token = read_token("Token: ")?;
+        match token.is_empty() {
+            true => eprintln!("The token can't be empty"),
+            false => break token,
+        }
+    };
+
+    let haos_conn = HomeAssistantConnection::new(url.clone(), String::from("haoscli"));
+    haos_conn
+        .write()
+        .expect("Couldn't get the write lock on the token")
+        .set_long_live_token(token.clone());
+    let status = {
+        let conn = haos_conn.read().expect("Couldn't get the read lock");
+        rt.block_on(conn.get_api_status())
+    };
+    match status {
+        Ok(message) => println!("Connected: {}", message),
+        Err(e) => {
+            eprintln!("Couldn't connect to {}: {}", url, e);
+            if !confirm("Save the config anyway?")? {
+                return Err(String::from("Nothing was written"));
+            }
+        }
+    }
+
+    write_config(path, &url, &token).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// The defaults are written out commented so there's something to go off of when changing them.
+pub fn write_config(path: &Path, url: &str, token: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = format!(
+        r#"url = {}
+token = {}
+# The token can come from a file or a command instead, IE:
+# token_file = "~/.secrets/haos-token"
+# token_command = "pass show home-assistant"
+
+# client_id = "haoscli"
+# log_level = "Warn"     # Off, Error, Warn, Info, Debug or Trace
+# poll_rate = 1000       # milliseconds between fetches in the UI
+# log_file = "~/.local/state/haoscli/haoscli.log"
+# log_max_size = 1024    # kilobytes before the log is rotated, 0 never rotates
+# cache_file = "~/.cache/haoscli/cache.json"
+# queue_offline = false  # hold changes made while offline & send them once it's back
+# queue_max_age = 300    # seconds before a queued change is dropped instead
+
+# How often each list in the UI is fetched again, in milliseconds. poll_rate for anything left out.
+# [refresh]
+# states = 2000
+# services = 600000
+# events = 600000
+"#,
+        toml::Value::String(url.to_string()),
+        toml::Value::String(token.to_string())
+    );
+
+    let mut file = fs::File::create(path)?;
+    // It holds the token, nobody else needs to read it. Done before writing so it's never out there.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents.as_bytes())
+}
+
+fn prompt(question: &str) -> Result<String, String> {
+    print!("{}", question);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(0) => Err(String::from("Stopped before the config was finished")),
+        Ok(_) => Ok(answer.trim().to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Doesn't echo the token when there's someone typing, but still reads it from a pipe.
+fn read_token(question: &str) -> Result<String, String> {
+    match io::stdin().is_terminal() {
+        true => rpassword::prompt_password(question)
+            .map(|token| token.trim().to_string())
+            .map_err(|e| e.to_string()),
+        false => prompt(question),
+    }
+}
+
+fn confirm(question: &str) -> Result<bool, String> {
+    let answer = prompt(&format!("{} [y/N] ", question))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
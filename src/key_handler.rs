@@ -1,47 +1,25 @@
-use std::sync::{Arc, Condvar, Mutex};
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
 use std::time::Duration;
 
-use crate::ui_types::{Pane, PopUpPane, UiState, ServicesPopUpElement, BuildTable};
+use crate::actions::{Action, Change, Command, Quit};
+use crate::reducer::refresh;
+use crate::ui_types::{backup_file, BackupAction, Pane, PopUpPane, UiState};
 
-use crossterm::event::KeyModifiers;
+use crossterm::event::{KeyEvent, KeyModifiers};
 use crossterm::event::{self, Event, KeyCode};
 
-use haoscli::types::Service;
-use log::{debug, info, warn};
-use tui::layout::Rect;
+use haoscli::types::{AddonAction, Service, StatisticsPeriod};
+use log::{debug, info, warn, LevelFilter};
+use tokio_util::sync::CancellationToken;
 use tui::widgets::TableState;
 
 const REFRESH_RATE: u64 = 100;
 
-// Helper function to drop the first paramater and call the function in second paramater and
-// optional arguments provided in later arguments
-// This is used to drop the state and call the function as such pattern is found redundant while
-// calling event handeling closure where unlocked state needs to be droppped before calling the
-// corresponding handler
-// Macro is ripped right from the https://github.com/sudipghimire533/ytui-music repo. S/O to them.
-macro_rules! drop_and_call {
-    // This will call the function in passe in second argument
-    // passed function will not accept any argument
-    ($state: expr, $callback: expr) => {{
-        debug!("Dropping the state");
-        drop($state);
-        std::mem::drop($state);
-        $callback()
-    }};
-    // This will call the function recived in second argument and pass the later arguments as that
-    // function paramater
-    ($state: expr, $callback: expr, $($args: expr)*) => {{
-        debug!("Dropping the state");
-        std::mem::drop($state);
-        $callback( $($args)* )
-    }};
-}
-
 /// which direction the keypress is.
 enum KeyDirection {
     Up,
     Down,
-    Initial,
 }
 
 /// Helper function to determine the next index for indexable widgets.
@@ -52,299 +30,422 @@ fn next_index(current: usize, list_size: usize, direction: KeyDirection) -> usiz
     match direction {
         KeyDirection::Up => (current.checked_sub(1).unwrap_or(list_size - 1)) % list_size,
         KeyDirection::Down => (current + 1) % list_size,
-        KeyDirection::Initial => current,
     }
 }
 
-/// Async function which handles the key press management and then updates the UI state for
-/// drawing.
-pub async fn key_handler(state_og: &mut Arc<Mutex<UiState>>, notifier: &mut Arc<Condvar>) {
-    let quit = || -> bool {
-        let mut state = state_og
-            .lock()
-            .expect("Could not quit, we couldn't lock the UI");
-        state.active = Pane::None;
-        notifier.notify_all();
-        true
-    };
+/// Which add-on action, if any, a key press maps to while the add-ons pane is active.
+fn addon_action_for_key(ch: char) -> Option<AddonAction> {
+    match ch {
+        's' => Some(AddonAction::Start),
+        'S' => Some(AddonAction::Stop),
+        'r' => Some(AddonAction::Restart),
+        'u' => Some(AddonAction::Update),
+        _ => None,
+    }
+}
+
+/// Which statistics period, if any, a key press maps to while the energy pane is active.
+fn energy_period_for_key(ch: char) -> Option<StatisticsPeriod> {
+    match ch {
+        'h' => Some(StatisticsPeriod::Hour),
+        'd' => Some(StatisticsPeriod::Day),
+        'w' => Some(StatisticsPeriod::Week),
+        'm' => Some(StatisticsPeriod::Month),
+        _ => None,
+    }
+}
+
+/// The most verbose level, if any, a key press picks while the log pane is active.
+fn log_level_for_key(ch: char) -> Option<LevelFilter> {
+    match ch {
+        'e' => Some(LevelFilter::Error),
+        'w' => Some(LevelFilter::Warn),
+        'i' => Some(LevelFilter::Info),
+        'd' => Some(LevelFilter::Debug),
+        't' => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
 
-    let event_list_move = |direction: KeyDirection| {
-        let mut state = match state_og.lock() {
-            Ok(v) => {
-                info!("Grabbed the state");
-                v
+/// Reads the terminal's events and passes them on to the reducer. Reading blocks so this gets a
+/// blocking task of its own, it stops once `shutdown` is cancelled or there's no one left to send to.
+pub fn key_handler(actions: Sender<Action>, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        if !event::poll(Duration::from_millis(REFRESH_RATE)).unwrap() {
+            continue;
+        }
+        let action = match event::read().unwrap() {
+            Event::Key(key) => {
+                debug!("Pressed {:?}", key);
+                Action::Key(key)
             }
-            Err(e) => {
-                info!("Couldn't grab the lock to move the list???: {}", e);
-                return;
+            Event::Resize(..) => {
+                debug!("Window was resized");
+                Action::Resize
             }
+            Event::FocusLost => {
+                debug!("Focus lost");
+                continue;
+            }
+            _ => continue,
         };
-        debug!(
-            "state.events.selected:\t{}",
-            state
-                .events
-                .1
-                .selected()
-                .expect("Couldn't get what row was selected")
-        );
-        let move_to_index = match state.events.1.selected() {
-            None => 0,
-            Some(current) => next_index(current, state.events.0.len(), direction),
-        };
-        state.events.1.select(Some(move_to_index));
-        debug!(
-            "state.events.selected:\t{}",
-            state.events.1.selected().unwrap()
-        );
-        drop(state);
-        notifier.notify_all();
-    };
-
-    let states_list_move = |direction: KeyDirection| {
-        let mut state = state_og.lock().expect("Couldn't grab the state to move.");
-        debug!(
-            "state.states.selected:\t{}",
-            state
-                .states
-                .1
-                .selected()
-                .expect("Couldn't get the row that was selected")
-        );
-        let move_to_index = match state.states.1.selected() {
-            None => 0,
-            Some(current) => next_index(current, state.states.0.len(), direction),
-        };
-        state.states.1.select(Some(move_to_index));
-        debug!(
-            "state.states.selected:\t{}",
-            state
-                .states
-                .1
-                .selected()
-                .expect("Couldn't get the row that was selected")
-        );
-        drop(state);
-        notifier.notify_all();
-    };
+        if actions.send(action).is_err() {
+            info!("The UI has closed, no more keys to handle");
+            break;
+        }
+    }
+}
 
-    let services_table_move = |direction: KeyDirection| {
-        let mut state = state_og.lock().expect("Couldn't grab the UI state");
-        debug!(
-            "state.services.selected:\t{}",
-            state
-                .services
-                .1
-                .selected()
-                .expect("Couldn't get the row that is selected")
-        );
-        let move_to_index = match state.services.1.selected() {
-            None => 0,
-            Some(current) => next_index(current, state.services.0.len(), direction),
-        };
-        state.services.1.select(Some(move_to_index));
-        debug!(
-            "state.services.selected:\t{}",
-            state
-                .services
-                .1
-                .selected()
-                .expect("Couldn't get the row that is selected")
-        );
-        drop(state);
-        notifier.notify_all();
-    };
+/// Works out what a key press means for the pane that's showing. Whatever has to be sent to Home
+/// Assistant because of it comes back as commands for the fetcher.
+pub fn handle_key(state: &mut UiState, key: KeyEvent) -> Vec<Command> {
+    let holding_ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let quit_armed = std::mem::take(&mut state.quit_armed);
+    match key.code {
+        KeyCode::Up => handle_up_or_down(state, KeyDirection::Up),
+        KeyCode::Down => handle_up_or_down(state, KeyDirection::Down),
+        KeyCode::Enter => return handle_enter(state),
+        KeyCode::Esc => handle_escape(state),
+        KeyCode::Backspace => {
+            state.input_pane.pop();
+        }
+        KeyCode::Delete => return handle_delete(state),
+        KeyCode::Char(ch) => return handle_char(state, ch, holding_ctrl, quit_armed),
+        _ => (),
+    }
+    Vec::new()
+}
 
-    let services_popup_table_move = |direction: KeyDirection| {
-        let mut state = state_og.lock().expect("Couldn't grab the UI State");
+fn handle_char(state: &mut UiState, ch: char, holding_ctrl: bool, quit_armed: bool) -> Vec<Command> {
+    // Works everywhere, pop ups included, since that's where the polling's paused.
+    if ch == 'r' && holding_ctrl {
+        return refresh(state);
+    }
+    // Raw mode swallows the SIGINT, so Ctrl+c is a key like any other.
+    if ch == 'c' && holding_ctrl {
+        ask_to_quit(state, Quit::Interrupt, quit_armed);
+        return Vec::new();
+    }
+    if state.editing() {
+        debug!("The active pane is in the pop up");
+        state.input_pane.push(ch);
+    } else if let (Pane::Addons, false, Some(action)) = (&state.active, holding_ctrl, addon_action_for_key(ch)) {
+        return handle_addon_action(state, action);
+    } else if let (Pane::Energy, false, Some(period)) = (&state.active, holding_ctrl, energy_period_for_key(ch)) {
+        state.energy_period = period;
+        state.energy_status = format!("loading {:?}...", period);
+    } else if let (Pane::Logs, false, Some(level)) = (&state.active, holding_ctrl, log_level_for_key(ch)) {
+        state.log_pane.level = level;
+        state.log_pane.scroll = 0;
+    } else if ch == 'l' && holding_ctrl {
+        toggle_logs(state);
+    } else if ch == 'o' && holding_ctrl {
+        toggle_queue(state);
+    } else if state.active == Pane::Queue && !holding_ctrl && ch == 'd' {
+        cancel_queued(state);
+    } else if state.active == Pane::Backups && !holding_ctrl && (ch == 'n' || ch == 'd') {
+        return handle_backup_key(state, ch);
+    } else if ch == 'q' {
+        ask_to_quit(state, Quit::Key, quit_armed);
+    } else if ch == 'e' && holding_ctrl {
+        state.active = Pane::Events;
+    } else if ch == 's' && holding_ctrl {
+        state.active = Pane::Services;
+    } else if ch == 'x' && holding_ctrl {
+        state.active = Pane::States;
+    } else if ch == 'a' && holding_ctrl {
+        state.active = Pane::Addons;
+    } else if ch == 'b' && holding_ctrl {
+        state.active = Pane::Backups;
+    } else if ch == 'g' && holding_ctrl {
+        state.active = Pane::Energy;
+    }
+    Vec::new()
+}
 
+fn handle_up_or_down(state: &mut UiState, direction: KeyDirection) {
+    match state.active {
+        Pane::Events => {
+            let move_to_index = match state.events.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.events.0.len(), direction),
+            };
+            state.events.1.select(Some(move_to_index));
+            debug!("state.events.selected:\t{:?}", state.events.1.selected());
+        }
+        Pane::States => {
+            let move_to_index = match state.states.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.states.0.len(), direction),
+            };
+            state.states.1.select(Some(move_to_index));
+            debug!("state.states.selected:\t{:?}", state.states.1.selected());
+        }
+        Pane::Services => {
+            let move_to_index = match state.services.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.services.0.len(), direction),
+            };
+            state.services.1.select(Some(move_to_index));
+            debug!("state.services.selected:\t{:?}", state.services.1.selected());
+        }
+        Pane::PopUp(PopUpPane::Services) => {
+            debug!("state.services_popup.0.services:\t{:?}", state.services_popup.0.services);
+            let Some(state_map) = state.services_popup.0.services.as_object() else {
+                return;
+            };
+            let move_to_index = match state.services_popup.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state_map.len(), direction),
+            };
+            if let Some(selected) = state_map.keys().nth(move_to_index) {
+                state.services_popup_selected = selected.clone();
+            }
+            debug!("state.services_popup_selected:\t{}", state.services_popup_selected);
+            state.services_popup.1.select(Some(move_to_index));
+        }
+        Pane::Addons => {
+            let move_to_index = match state.addons.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.addons.0.len(), direction),
+            };
+            state.addons.1.select(Some(move_to_index));
+            debug!("state.addons.selected:\t{:?}", state.addons.1.selected());
+        }
+        // Up scrolls further back into the logs, down heads back toward the newest line.
+        Pane::PopUp(PopUpPane::Addons) => {
+            state.addon_logs.1 = match direction {
+                KeyDirection::Up => state.addon_logs.1.saturating_add(1),
+                KeyDirection::Down => state.addon_logs.1.saturating_sub(1),
+            };
+        }
+        Pane::Backups => {
+            let move_to_index = match state.backups.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.backups.0.len(), direction),
+            };
+            state.backups.1.select(Some(move_to_index));
+            state.backup_delete_armed = None;
+            state.backup_overwrite_armed = None;
+        }
+        Pane::Queue => {
+            let move_to_index = match state.queue.1.selected() {
+                None => 0,
+                Some(current) => next_index(current, state.queue.0.len(), direction),
+            };
+            state.queue.1.select(Some(move_to_index));
+        }
+        // Same as the add-on logs.
+        Pane::Logs => {
+            state.log_pane.scroll = match direction {
+                KeyDirection::Up => state.log_pane.scroll.saturating_add(1),
+                KeyDirection::Down => state.log_pane.scroll.saturating_sub(1),
+            };
+        }
+        _ => (),
+    }
+}
 
-        debug!("state.services_popup.0.services:\t{:?}", state.services_popup.0.services);
-        debug!("state.services_popup.1.selected():\t{:?}", state.services_popup.1.selected());
+/// Ctrl+l opens the log pane over whatever's showing & closes it back to there.
+fn toggle_logs(state: &mut UiState) {
+    if state.active == Pane::Logs {
+        state.active = state.log_pane.return_to.clone();
+    } else {
+        state.log_pane.return_to = state.active.clone();
+        state.log_pane.scroll = 0;
+        state.active = Pane::Logs;
+    }
+}
 
-        let state_map = state.services_popup.0.services.as_object().unwrap();
-        
-        let move_to_index = match state.services_popup.1.selected() {
-            None => 0,
-            Some(current) => next_index(current, state_map.len(), direction),
-        };
+/// Ctrl+o opens the offline queue over whatever's showing & closes it back to there.
+fn toggle_queue(state: &mut UiState) {
+    if state.active == Pane::Queue {
+        state.active = state.queue_return_to.clone();
+    } else {
+        state.queue_return_to = state.active.clone();
+        state.queue.1.select(Some(0));
+        state.active = Pane::Queue;
+    }
+}
 
-        state.services_popup_selected = state_map.keys().nth(move_to_index).unwrap().into();
-        debug!("state.services_popup_selected:\t{}", state.services_popup_selected);
-        state.services_popup.1.select(Some(move_to_index));
-        debug!("state.services_popup.1.selected():\t{:?}", state.services_popup.1.selected());
-        drop(state);
-        notifier.notify_all();
+/// Takes the selected change out of the queue so it's never sent.
+fn cancel_queued(state: &mut UiState) {
+    let Some(idx) = state.queue.1.selected().filter(|idx| *idx < state.queue.0.len()) else {
+        return;
     };
+    let cancelled = state.queue.0.remove(idx);
+    info!("Cancelled {}, it won't be sent", cancelled.change.describe());
+    if idx >= state.queue.0.len() {
+        state.queue.1.select(Some(idx.saturating_sub(1)));
+    }
+}
 
-    let handle_up_or_down = |direction: KeyDirection| {
-        let state = state_og.lock().expect("Couldn't lock on the UI");
-        match state.active {
-            Pane::Events => {
-                info!("Matched the eventPane");
-                drop_and_call!(state, event_list_move, direction);
-            }
-            Pane::States => {
-                info!("Matched the states pane");
-                drop_and_call!(state, states_list_move, direction);
-            }
-            Pane::Services => {
-                drop_and_call!(state, services_table_move, direction);
-            }
-            Pane::PopUp(PopUpPane::Services) => {
-                drop_and_call!(state, services_popup_table_move, direction);
+/// Enter opens the pop up for whatever's selected, or sends off what was typed into one.
+fn handle_enter(state: &mut UiState) -> Vec<Command> {
+    let has_selected = |selected: Option<usize>, len: usize| selected.is_some_and(|idx| idx < len);
+    match state.active {
+        Pane::Events if has_selected(state.events.1.selected(), state.events.0.len()) => {
+            state.active = Pane::PopUp(PopUpPane::Events)
+        }
+        Pane::Services if has_selected(state.services.1.selected(), state.services.0.len()) => {
+            state.active = Pane::PopUp(PopUpPane::Services);
+            let sel_service: &Service = state.get_selected_service();
+            let mut popup_state = TableState::default();
+            popup_state.select(Some(0));
+            state.services_popup = (sel_service.clone(), popup_state);
+        }
+        Pane::States if has_selected(state.states.1.selected(), state.states.0.len()) => {
+            state.active = Pane::PopUp(PopUpPane::States)
+        }
+        // Nothing to open while the list's empty, IE: offline with nothing cached.
+        Pane::Events | Pane::Services | Pane::States => (),
+        Pane::Addons => {
+            state.active = Pane::PopUp(PopUpPane::Addons);
+            state.addon_logs = (String::new(), 0);
+        }
+        Pane::PopUp(PopUpPane::Addons) => (),
+        Pane::Backups => (),
+        Pane::Energy => (),
+        Pane::Logs => (),
+        Pane::Queue => (),
+        Pane::PopUp(PopUpPane::Backups) => {
+            let name = std::mem::take(&mut state.input_pane);
+            state.backup_status = format!("creating backup {}...", name);
+            state.active = Pane::Backups;
+            return state.send(Change::Backup(BackupAction::Create(name)));
+        }
+        // The command carries everything it needs, so closing the pop up straight after doesn't
+        // stop it from being sent.
+        Pane::PopUp(PopUpPane::States) => {
+            let Some(selected_state) = state.states.1.selected().and_then(|idx| state.states.0.get(idx)) else {
+                return Vec::new();
+            };
+            match serde_json::from_str::<HashMap<String, String>>(&state.input_pane) {
+                Ok(payload) => {
+                    let change = Change::SetState(selected_state.clone(), payload);
+                    state.input_pane.clear();
+                    return state.send(change);
+                }
+                // Left in the box so it can be fixed up.
+                Err(e) => warn!("Couldn't parse {} as json: {}", state.input_pane, e),
             }
-            Pane::None => _ = quit(),
-            _ => (),
         }
+        Pane::PopUp(PopUpPane::Services) => {
+            let Some(selected_service) = state.services.1.selected().and_then(|idx| state.services.0.get(idx)) else {
+                return Vec::new();
+            };
+            let change = Change::CallService {
+                domain: selected_service.domain.clone(),
+                service: state.services_popup_selected.clone(),
+                entity_id: std::mem::take(&mut state.input_pane),
+            };
+            debug!("Calling {:?}", change);
+            return state.send(change);
+        }
+        Pane::PopUp(PopUpPane::Events) => warn!("Currently don't support sending an event, sorry"),
+        Pane::PopUp(PopUpPane::None) => (),
+        Pane::None => debug!("Trying to hit enter when we have no active pane, ignoring as we should be closing."),
     };
+    debug!("Active pane should be a popup: {:?}", state.active);
+    Vec::new()
+}
 
-    let handle_pane_switch = |switch_to_pane: Pane| {
-        let mut state = state_og.lock().expect("Couldn't lock on the UI");
-        state.active = switch_to_pane;
-        notifier.notify_all();
-    };
-
-    // Need a way to handle hitting enter to bring up the correct pop up for a given service.
-    let handle_enter = || {
-        // Match on what the active pane is and then mark the active as the pane.
-        //let mut state = state_og.lock().map_err(|_| {warn!("Couldn't lock the state")});
-        let mut state = state_og.lock().expect("Couldn't lock on the UI");
-        match state.active {
-            Pane::Events => state.active = Pane::PopUp(PopUpPane::Events),
-            Pane::Services => {
-                state.active = Pane::PopUp(PopUpPane::Services);
-                let sel_service: &Service = state.get_selected_service();
-                let mut popup_state = TableState::default();
-                popup_state.select(Some(0));
-                state.services_popup = (sel_service.clone(), popup_state);
-            },
-            Pane::States => state.active = Pane::PopUp(PopUpPane::States),
-            Pane::PopUp(_) => state.input_pane.1 = true,
-            Pane::Search => todo!("Not implemented"),
-            Pane::None => debug!("Trying to hit enter when we have no active pane, ignoring as we should be closing."),
-        };
-        debug!("Active pane should be a popup: {:?}", state.active);
-        notifier.notify_all();
-    };
+// Need a way to exit a popup, we'll set the pane back to the non-pop up version of whatever we
+// opened last.
+fn handle_escape(state: &mut UiState) {
+    match state.active {
+        Pane::PopUp(PopUpPane::Events) => state.active = Pane::Events,
+        Pane::PopUp(PopUpPane::Services) => state.active = Pane::Services,
+        Pane::PopUp(PopUpPane::States) => state.active = Pane::States,
+        Pane::PopUp(PopUpPane::Addons) => state.active = Pane::Addons,
+        Pane::PopUp(PopUpPane::Backups) => state.active = Pane::Backups,
+        Pane::PopUp(PopUpPane::None) => debug!("tf???"),
+        Pane::Logs => state.active = state.log_pane.return_to.clone(),
+        Pane::Queue => state.active = state.queue_return_to.clone(),
+        _ => debug!("Ignoring escape press for non-pop up panes"),
+    }
+    state.input_pane.clear(); // THIS IS BAD BUT HEY I'M WORKING TOWARD AN MVP. WE WILL HAVE TO
+                              // ACCEPT THIS AS A REALITY.
+}
 
-    /*
-    let handle_popup_enter = || {
-        let mut state = state_og.lock().expect("Couldn't Lock the UI");
-        match state.active {
-            Pane::PopUp(PopUpPane::Services) => {
-                
-            }, 
+fn handle_addon_action(state: &mut UiState, action: AddonAction) -> Vec<Command> {
+    let selected_slug = state
+        .addons
+        .1
+        .selected()
+        .and_then(|idx| state.addons.0.get(idx))
+        .map(|addon| addon.slug.clone());
+    match selected_slug {
+        Some(slug) => {
+            state.addon_status = format!("{:?} requested", action);
+            state.send(Change::Addon(slug, action))
         }
-    };
-    */
+        None => Vec::new(),
+    }
+}
 
-    // Need a way to exit a popup, we'll set the pane back to the non-pop up version of whatever we
-    // opened last.
-    let handle_escape = || {
-        let mut state = state_og.lock().expect("Couldn't lock on the UI");
-        match state.active {
-            Pane::PopUp(PopUpPane::Events) => state.active = Pane::Events,
-            Pane::PopUp(PopUpPane::Services) => state.active = Pane::Services,
-            Pane::PopUp(PopUpPane::States) => state.active = Pane::States,
-            Pane::PopUp(PopUpPane::None) => debug!("tf???"),
-            _ => debug!("Ignoring escape press for non-pop up panes"),
+fn handle_backup_key(state: &mut UiState, ch: char) -> Vec<Command> {
+    let selected_slug = state
+        .backups
+        .1
+        .selected()
+        .and_then(|idx| state.backups.0.get(idx))
+        .map(|backup| backup.slug.clone());
+    match (ch, selected_slug) {
+        ('n', _) => {
+            state.input_pane.clear();
+            state.active = Pane::PopUp(PopUpPane::Backups);
         }
-        state.input_pane = (String::from(""), false); // THIS IS BAD BUT HEY I'M WORKING TOWARD AN
-                                                      // MVP. WE WILL HAVE TO ACCEPT THIS AS A
-                                                      // REALITY. 
-        notifier.notify_all();
-    };
-
-    let activate_search = || {
-        let mut state = state_og.lock().expect("Couldn't lock on the state");
-        match state.active {
-            Pane::PopUp(_) => state.active = Pane::Search,
-            _ => (),
+        ('d', Some(slug)) => {
+            // Same as deleting, a second press is needed before an earlier download is lost.
+            let file = backup_file(&slug);
+            if file.exists() && state.backup_overwrite_armed.as_ref() != Some(&slug) {
+                state.backup_status = format!("{} is already there, press d again to overwrite it", file.display());
+                state.backup_overwrite_armed = Some(slug);
+                return Vec::new();
+            }
+            state.backup_overwrite_armed = None;
+            state.backup_status = format!("downloading {}...", slug);
+            return state.send(Change::Backup(BackupAction::Download(slug)));
         }
-        notifier.notify_all();
-    };
-
-    let handle_popup_input = |ch| {
-        debug!("Handling popup input");
-        let mut state = state_og.lock().expect("Couldn't lock the state");
-        state.input_pane.0.push(ch);
-        notifier.notify_all();
-    };
+        _ => (),
+    }
+    Vec::new()
+}
 
-    let handle_backspace = || {
-        debug!("Handling the backspace");
-        let mut state = state_og.lock().expect("Couldn't lock the state");
-        state.input_pane.0.pop();
-        notifier.notify_all();
-    };
+/// Quits, unless there are changes queued that quitting would drop. Then it takes a second press.
+fn ask_to_quit(state: &mut UiState, quit: Quit, quit_armed: bool) {
+    if quit_armed || state.queue.0.is_empty() {
+        state.quit(quit);
+        return;
+    }
+    info!("Asking before dropping {} queued changes", state.queue.0.len());
+    state.quit_armed = true;
+}
 
-    'listener_loop: loop {
-        if event::poll(Duration::from_millis(REFRESH_RATE)).unwrap() {
-            match event::read().unwrap() {
-                Event::Key(key) => {
-                    let holding_ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
-                    match key.code {
-                        KeyCode::Up => {
-                            debug!("Pressed Up");
-                            handle_up_or_down(KeyDirection::Up);
-                        }
-                        KeyCode::Down => {
-                            debug!("Pressed down");
-                            handle_up_or_down(KeyDirection::Down);
-                        }
-                        KeyCode::Enter => {
-                            debug!("Pressed Enter");
-                            handle_enter();
-                        }
-                        KeyCode::Esc => {
-                            debug!("Pressed Escape");
-                            handle_escape();
-                        }
-                        KeyCode::Backspace => {
-                            debug!("Pressed backspace");
-                            handle_backspace();
-                        }
-                        KeyCode::Char(ch) => {
-                            let active_pane = state_og.lock().expect("Could be anything").active.clone();
-                            let in_pop_up = match active_pane {
-                                Pane::PopUp(PopUpPane::Events) => true,
-                                Pane::PopUp(PopUpPane::States) => true,
-                                Pane::PopUp(PopUpPane::Services) => true,
-                                Pane::PopUp(PopUpPane::None) => false,
-                                _ => false, 
-                            };
-                            if in_pop_up {
-                                debug!("The active pane is in the pop up");
-                                handle_popup_input(ch);
-                            } else if ch == 'q' {
-                                info!("Got quit keypress. Quitting");
-                                if quit() {
-                                    break 'listener_loop;
-                                }
-                            } else if ch == 'e' && holding_ctrl {
-                                handle_pane_switch(Pane::Events);
-                            } else if ch == 's' && holding_ctrl {
-                                handle_pane_switch(Pane::Services);
-                            } else if ch == 'x' && holding_ctrl {
-                                handle_pane_switch(Pane::States);
-                            } else if ch == '/' {
-                                activate_search();
-                            }
-                        }
-                        _ => {
-                            notifier.notify_all();
-                        }
-                    }
-                }
-                Event::FocusLost => {
-                    debug!("Focus lost");
-                }
-                Event::Resize(..) => {
-                    debug!("Window was resized");
-                    notifier.notify_all();
-                }
-                _ => {}
-            }
+// Deleting needs two presses on the same backup so a stray key doesn't throw one away.
+fn handle_delete(state: &mut UiState) -> Vec<Command> {
+    // No need for the second press here, nothing's lost that can't be done again.
+    if state.active == Pane::Queue {
+        cancel_queued(state);
+    }
+    if state.active != Pane::Backups {
+        return Vec::new();
+    }
+    let selected = state
+        .backups
+        .1
+        .selected()
+        .and_then(|idx| state.backups.0.get(idx))
+        .map(|backup| (backup.slug.clone(), backup.name.clone()));
+    if let Some((slug, name)) = selected {
+        if state.backup_delete_armed.as_ref() == Some(&slug) {
+            state.backup_delete_armed = None;
+            state.backup_status = format!("deleting {}...", name);
+            return state.send(Change::Backup(BackupAction::Delete(slug)));
         }
+        state.backup_delete_armed = Some(slug);
+        state.backup_status = format!("press Del again to delete {}", name);
     }
+    Vec::new()
 }
@@ -1,11 +1,30 @@
-use std::{sync::Arc, sync::RwLock, sync::Weak, thread::Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{sync::Arc, sync::RwLock, sync::Weak};
 
 use log::{debug, info, trace, warn};
 
 use serde::{Deserialize, Serialize};
 
-use types::{HomeAssistantConnection, Token};
+use cassette::Cassette;
+use error::{Error, Result};
+use types::{HomeAssistantConnection, TlsConfig, Token};
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cassette;
+pub mod condition;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+pub mod error;
+mod supervisor;
+mod tls;
 pub mod types;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// How long a request gets to be answered by default, on top of connecting (see
+/// `tls::CONNECT_TIMEOUT`).
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
 impl HomeAssistantConnection {
     pub fn new(url: String, client_id: String) -> Arc<RwLock<Self>> {
@@ -16,6 +35,10 @@ impl HomeAssistantConnection {
             client_id,
             lock: Weak::new(),
             retries: 30,
+            client: reqwest::Client::new(),
+            tls: TlsConfig::default(),
+            cassette: None,
+            request_timeout: REQUEST_TIMEOUT,
         }));
 
         ret.write().unwrap().lock = Arc::downgrade(&ret);
@@ -27,18 +50,55 @@ impl HomeAssistantConnection {
         self.token = Token::LongLivedToken(token);
     }
 
+    /// Rebuilds the client with the given certificates and proxy. Everything sent after this,
+    /// websockets included, uses them.
+    pub fn set_tls_config(&mut self, tls: TlsConfig) -> Result<()> {
+        self.client = tls.build_client(&self.host()?)?;
+        self.tls = tls;
+        Ok(())
+    }
+
+    /// Writes every request & what it got back to `path` from here on, see `cassette`.
+    pub fn record_to(&mut self, path: PathBuf) -> Result<()> {
+        self.cassette = Some(Arc::new(Cassette::record(path)?));
+        Ok(())
+    }
+
+    /// Answers every request from the cassette at `path` from here on, nothing goes to the server.
+    pub fn replay_from(&mut self, path: &Path) -> Result<()> {
+        self.cassette = Some(Arc::new(Cassette::replay(path)?));
+        Ok(())
+    }
+
+    /// The host part of our url, IE: homeassistant.local
+    fn host(&self) -> Result<String> {
+        let url = reqwest::Url::parse(&self.url)
+            .map_err(|e| Error::Config(format!("{} isn't a valid url: {}", self.url, e)))?;
+        url.host_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Config(format!("{} doesn't have a host", self.url)))
+    }
+
+    /// Asks `/api/` whether the API is up, which also checks the token. Gives back its message,
+    /// IE: "API running."
+    pub async fn get_api_status(&self) -> Result<String> {
+        let req = self.build_base_get_request("/");
+        let resp = check_status(self.send(req).await?).await?;
+
+        #[derive(Deserialize)]
+        struct Response {
+            message: String,
+        }
+
+        let resp_json: Response = resp.json().await?;
+        Ok(resp_json.message)
+    }
+
     pub async fn get_events(&self) -> Result<Vec<types::Event>> {
         let req = self.build_base_get_request("/events");
-        let resp = req.send().await.unwrap();
-        match resp.error_for_status_ref() {
-            Ok(_) => (),
-            Err(err) => {
-                warn!("err.status: {}", err.status().unwrap_or_default());
-                assert_eq!(err.status(), Some(reqwest::StatusCode::UNAUTHORIZED));
-            }
-        };
+        let resp = check_status(self.send(req).await?).await?;
 
-        let resp_json: Vec<types::Event> = resp.json().await.expect("Could not convert to json");
+        let resp_json: Vec<types::Event> = resp.json().await?;
 
         Ok(resp_json)
     }
@@ -50,44 +110,32 @@ impl HomeAssistantConnection {
     ) -> Result<String> {
         let api = format!("{}/api/events/{}", self.url, event_type);
         let str_token = self.get_token();
-        let req = reqwest::Client::new()
+        let mut req = self.client
             .post(api.as_str())
             .header("content-type", "application/json")
             .bearer_auth(str_token);
 
         if let Some(data) = event_data {
-            debug!("We're just dropping this data for now: {}", &data);
-            //req = req.json(&data);
+            debug!("Firing {} with: {}", event_type, &data);
+            req = req.json(&data);
         }
 
-        let resp = match req.send().await {
-            Ok(v) => v,
-            Err(_) => panic!("Couldn't send the message"),
-        };
+        let resp = check_status(self.send(req).await?).await?;
 
         #[derive(Serialize, Deserialize, Debug)]
         struct Response {
             message: String,
         }
 
-        let resp_json: Response = match resp.json().await {
-            Ok(v) => v,
-            Err(_) => panic!("Couldn't parse the json response"),
-            };
-            Ok(resp_json.message)
+        let resp_json: Response = resp.json().await?;
+        Ok(resp_json.message)
     }
 
     pub async fn get_services(&self) -> Result<Vec<types::Service>> {
         let req = self.build_base_get_request("/services");
-        let resp = match req.send().await {
-            Ok(v) => v,
-            Err(_) => panic!("Could not get services response"),
-        };
+        let resp = check_status(self.send(req).await?).await?;
 
-        let resp_json: Vec<types::Service> = match resp.json().await {
-            Ok(v) => v,
-            Err(e) => panic!("Couldn't parse the json response:\t{}", e),
-        };
+        let resp_json: Vec<types::Service> = resp.json().await?;
         Ok(resp_json)
     }
 
@@ -107,42 +155,51 @@ impl HomeAssistantConnection {
 
 
         let str_token = self.get_token();
-        let mut req = reqwest::Client::new()
+        let mut req = self.client
             .post(api.as_str())
             .header("content-type", "application/json")
             .bearer_auth(str_token);
-        match entity {
-            Some(v) => req = req.json(&v),
-            None => (),
+        if let Some(v) = entity {
+            req = req.json(&v);
         }
 
 
         debug!("{:?}", req);
 
-        let resp = match req.send().await {
-            Ok(v) => v,
-            Err(e) => panic!("Could not post to service: {}", e),
-        };
+        let resp = check_status(self.send(req).await?).await?;
         info!("{:?}", resp);
 
-        let resp_json: serde_json::Value = match resp.json().await {
-            Ok(v) => v,
-            Err(_) => panic!("Couldn't parse the json response"),
-        };
+        let resp_json: serde_json::Value = resp.json().await?;
 
         Ok(resp_json)
     }
 
+    /// Calls `domain.service` with whatever `data` you like, IE: `{"entity_id": "light.desk",
+    /// "brightness": 120}`. Hands back the states that changed because of it.
+    pub async fn call_service(
+        &self,
+        domain: &str,
+        service: &str,
+        data: &serde_json::Value,
+    ) -> Result<Vec<types::State>> {
+        info!("Calling {}.{}", domain, service);
+        let req = self
+            .build_base_post_request(format!("/services/{}/{}", domain, service).as_str())
+            .json(data);
+        let resp = check_status(self.send(req).await?).await?;
+        Ok(resp.json().await?)
+    }
+
+    pub async fn get_state(&self, entity_id: &str) -> Result<types::State> {
+        let req = self.build_base_get_request(format!("/states/{}", entity_id).as_str());
+        let resp = check_status(self.send(req).await?).await?;
+        Ok(resp.json().await?)
+    }
+
     pub async fn get_states(&self) -> Result<Vec<types::State>> {
         let req = self.build_base_get_request("/states");
-        let resp = match req.send().await {
-            Ok(v) => v,
-            Err(e) => panic!("Couldn't not post to service: {}", e),
-        };
-        let resp_json: Vec<types::State> = resp
-            .json()
-            .await
-            .expect("Couldn't convert the list of entities from json to a vec of State structs");
+        let resp = check_status(self.send(req).await?).await?;
+        let resp_json: Vec<types::State> = resp.json().await?;
         for resp in &resp_json {
             trace!("{:?}", resp);
         }
@@ -155,32 +212,93 @@ impl HomeAssistantConnection {
         payload: types::RequestStateStruct,
     ) -> Result<types::State> {
         let req = self
-            .build_base_put_request(format!("/states/{}", state.entity_id.as_str()).as_str())
+            .build_base_post_request(format!("/states/{}", state.entity_id.as_str()).as_str())
             .json(&payload.state);
 
-        let resp = match req.send().await {
-            Ok(v) => v,
-            Err(e) => panic!("Couldn't set the state: {}", e),
-        };
+        let resp = self.send(req).await?;
         info!(
             "Set state for {} responded with HTTP code: {}",
             state.entity_id,
             resp.status()
         );
-        let resp_json: types::State = resp
-            .json()
-            .await
-            .expect("Couldn't convery the response of the state to a json");
+        let resp_json: types::State = check_status(resp).await?.json().await?;
 
         Ok(resp_json)
     }
 
-    fn build_base_put_request(&self, end_point: &str) -> reqwest::RequestBuilder {
+    /// Sets the state Home Assistant holds for `entity_id`, this doesn't talk to the device itself.
+    /// `attributes` replaces all of the existing ones when given.
+    pub async fn post_state(
+        &self,
+        entity_id: &str,
+        state: &str,
+        attributes: Option<&serde_json::Value>,
+    ) -> Result<types::State> {
+        let mut body = serde_json::json!({ "state": state });
+        if let Some(attributes) = attributes {
+            body["attributes"] = attributes.clone();
+        }
+        let req = self
+            .build_base_post_request(format!("/states/{}", entity_id).as_str())
+            .json(&body);
+        let resp = check_status(self.send(req).await?).await?;
+        Ok(resp.json().await?)
+    }
+
+    /// Renders a Jinja template on the server, IE: `{{ states('sun.sun') }}`.
+    pub async fn render_template(&self, template: &str) -> Result<String> {
+        let req = self
+            .build_base_post_request("/template")
+            .json(&serde_json::json!({ "template": template }));
+        let resp = check_status(self.send(req).await?).await?;
+        Ok(resp.text().await?)
+    }
+
+    /// Sends the request, or hands it to the cassette when there is one. It gets `request_timeout`
+    /// to be answered, so a server that accepts the connection & then hangs shows up as an error.
+    pub(crate) async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.send_within(req, Some(self.request_timeout)).await
+    }
+
+    /// Same as `send` but without the timeout, for add-on actions & backups which can take minutes
+    /// and would otherwise look like the server had gone away.
+    pub(crate) async fn send_unbounded(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.send_within(req, None).await
+    }
+
+    async fn send_within(
+        &self,
+        req: reqwest::RequestBuilder,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response> {
+        let mut request = req.build()?;
+        *request.timeout_mut() = timeout;
+        let Some(cassette) = &self.cassette else {
+            return Ok(self.client.execute(request).await?);
+        };
+        let token = match &self.token {
+            Token::LongLivedToken(token) => token.as_str(),
+            Token::None => "",
+        };
+        cassette.send(&self.client, request, &self.url, token).await
+    }
+
+    fn build_base_post_request(&self, end_point: &str) -> reqwest::RequestBuilder {
         let api = format!("{}/api{}", self.url, end_point);
         debug!("api: {}", api);
         let str_token = self.get_token();
-        reqwest::Client::new()
-            .get(api.as_str())
+        self.client
+            .post(api.as_str())
+            .header("content-type", "application/json")
+            .bearer_auth(str_token)
+    }
+
+    fn build_base_delete_request(&self, end_point: &str) -> reqwest::RequestBuilder {
+        let api = format!("{}/api{}", self.url, end_point);
+        debug!("api: {}", api);
+        let str_token = self.get_token();
+        self.client
+            .delete(api.as_str())
             .header("content-type", "application/json")
             .bearer_auth(str_token)
     }
@@ -189,7 +307,7 @@ impl HomeAssistantConnection {
         let api = format!("{}/api{}", self.url, end_point);
         debug!("api: {}", api);
         let str_token = self.get_token();
-        reqwest::Client::new()
+        self.client
             .get(api.as_str())
             .header("content-type", "application/json")
             .bearer_auth(str_token)
@@ -206,3 +324,16 @@ impl HomeAssistantConnection {
         }
     }
 }
+
+/// Turns a non success response into an `Error::Status`, keeping whatever body the server sent so
+/// the caller can show it.
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let url = resp.url().to_string();
+    let body = resp.text().await.unwrap_or_default();
+    warn!("{} responded with {}: {}", url, status, body);
+    Err(Error::Status(status, body))
+}
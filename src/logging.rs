@@ -0,0 +1,184 @@
+//! Where the logs go: a file that's rotated once it gets too big, plus the last few hundred lines
+//! kept in memory so the UI's log pane can show them without tailing the file elsewhere.
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// How far back the log pane can scroll.
+const RECENT_LINES: usize = 500;
+/// How many rotated files are kept next to the log, IE: `haoscli.log.1` to `haoscli.log.3`.
+const KEPT_FILES: u32 = 3;
+
+static RECENT: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+static LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// One line as the log pane shows it.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// 0 never rotates.
+    max_size: u64,
+}
+
+struct Logger {
+    /// What goes to the file, from `log_level` in the config.
+    file_level: LevelFilter,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+/// Sets up logging to `path`, rotating it once it's past `max_size` bytes. The pane always gets
+/// Info & up, even when the file is quieter, so there's something to look at without touching the
+/// config. If the file can't be opened that's handed back, but the pane still gets its lines.
+pub fn init(path: &Path, file_level: LevelFilter, max_size: u64) -> io::Result<()> {
+    let opened = RotatingFile::open(path, max_size);
+    let (file, result) = match opened {
+        Ok(file) => {
+            let _ = LOG_PATH.set(path.to_path_buf());
+            (Some(Mutex::new(file)), Ok(()))
+        }
+        Err(e) => (None, Err(e)),
+    };
+    let logger = Logger { file_level, file };
+    log::set_max_level(file_level.max(LevelFilter::Info));
+    // Only fails if something else got there first, which would be a bug in main.
+    log::set_logger(Box::leak(Box::new(logger))).expect("The logger was already set");
+    result
+}
+
+/// Where the log file ended up, if it could be opened.
+#[cfg(feature = "tui")]
+pub fn log_path() -> Option<&'static Path> {
+    LOG_PATH.get().map(PathBuf::as_path)
+}
+
+/// The lines kept in memory at `level` or more important, oldest first.
+#[cfg(feature = "tui")]
+pub fn recent(level: LevelFilter) -> Vec<LogLine> {
+    RECENT
+        .lock()
+        .map(|lines| lines.iter().filter(|line| line.level <= level).cloned().collect())
+        .unwrap_or_default()
+}
+
+/// `$XDG_STATE_HOME/haoscli/haoscli.log`, falling back to `~/.local/state`. Without a home it's
+/// left in the working directory like it used to be.
+pub fn default_log_file() -> PathBuf {
+    let state_dir = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".local/state"),
+            Err(_) => return PathBuf::from("haoscli.log"),
+        },
+    };
+    state_dir.join("haoscli").join("haoscli.log")
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.max_size > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            // Nowhere to report it, if rotating fails we just keep adding to the file we've got.
+            let _ = self.rotate();
+        }
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    /// Shuffles `haoscli.log.1` to `.2` & so on, dropping the oldest, then starts a fresh file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |n: u32| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        let _ = fs::remove_file(numbered(KEPT_FILES));
+        for n in (1..KEPT_FILES).rev() {
+            let from = numbered(n);
+            if from.exists() {
+                fs::rename(&from, numbered(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, numbered(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.file_level.max(LevelFilter::Info)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = LogLine {
+            time: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        if record.level() <= self.file_level {
+            if let Some(file) = &self.file {
+                if let Ok(mut file) = file.lock() {
+                    file.write_line(&format!(
+                        "{} [{}] {}: {}\n",
+                        line.time.format("%Y-%m-%dT%H:%M:%S%.3f"),
+                        line.level,
+                        line.target,
+                        line.message
+                    ));
+                }
+            }
+        }
+
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{RotatingFile, KEPT_FILES};
+
+/// A fresh directory for a test to write into.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("haoscli-logging-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn rotates_once_past_the_limit() {
+    let path = scratch_dir("rotate").join("haoscli.log");
+    let mut file = RotatingFile::open(&path, 20).unwrap();
+
+    file.write_line("first line\n");
+    assert_eq!(read(&path), "first line\n");
+    assert!(!numbered(&path, 1).exists());
+
+    file.write_line("second line\n");
+    assert_eq!(read(&numbered(&path, 1)), "first line\n");
+    // The fresh file starts out empty rather than keeping what was rotated away.
+    assert_eq!(read(&path), "second line\n");
+    assert_eq!(file.size, 12);
+}
+
+#[test]
+fn only_keeps_so_many_files() {
+    let path = scratch_dir("kept").join("haoscli.log");
+    let mut file = RotatingFile::open(&path, 10).unwrap();
+    for n in 0..=KEPT_FILES + 1 {
+        file.write_line(&format!("line {:04}\n", n));
+    }
+
+    assert_eq!(read(&path), format!("line {:04}\n", KEPT_FILES + 1));
+    for n in 1..=KEPT_FILES {
+        assert_eq!(read(&numbered(&path, n)), format!("line {:04}\n", KEPT_FILES + 1 - n));
+    }
+    assert!(!numbered(&path, KEPT_FILES + 1).exists());
+}
+
+#[test]
+fn picks_up_where_the_last_run_left_off() {
+    let path = scratch_dir("reopen").join("haoscli.log");
+    fs::write(&path, "from last time\n").unwrap();
+    let mut file = RotatingFile::open(&path, 20).unwrap();
+    assert_eq!(file.size, 15);
+
+    file.write_line("and now\n");
+    assert_eq!(read(&numbered(&path, 1)), "from last time\n");
+    assert_eq!(read(&path), "and now\n");
+}
+
+#[test]
+fn long_lines_still_go_in_an_empty_file() {
+    let path = scratch_dir("long").join("haoscli.log");
+    let mut file = RotatingFile::open(&path, 10).unwrap();
+    file.write_line("far longer than the limit\n");
+    assert_eq!(read(&path), "far longer than the limit\n");
+    assert!(!numbered(&path, 1).exists());
+}
+
+#[test]
+fn zero_never_rotates() {
+    let path = scratch_dir("zero").join("haoscli.log");
+    let mut file = RotatingFile::open(&path, 0).unwrap();
+    for _ in 0..1000 {
+        file.write_line("another line\n");
+    }
+    assert_eq!(file.size, 13_000);
+    assert_eq!(fs::metadata(&path).unwrap().len(), 13_000);
+    assert!(!numbered(&path, 1).exists());
+}
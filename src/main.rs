@@ -1,78 +1,54 @@
-use log::info;
+use log::warn;
 
-use haoscli::types::HomeAssistantConnection;
+use haoscli::types::{HomeAssistantConnection, TlsConfig};
 use tokio::io::Result;
 
-use serde::Deserialize;
-
-use std::{env, fs, thread::spawn};
+#[cfg(feature = "tui")]
+use log::info;
+#[cfg(feature = "tui")]
+use tokio_util::sync::CancellationToken;
 
-use std::sync::{Arc, Condvar, Mutex};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 
+mod check;
+mod cli;
+mod config;
+mod init;
+mod logging;
+mod output;
+mod repl;
+mod snapshot;
+mod watch;
+#[cfg(feature = "tui")]
+mod actions;
+#[cfg(feature = "tui")]
+mod cache;
+#[cfg(feature = "tui")]
+mod energy;
+#[cfg(feature = "tui")]
 mod fetcher;
+#[cfg(feature = "tui")]
 mod key_handler;
+#[cfg(feature = "tui")]
+mod reducer;
+#[cfg(feature = "tui")]
+mod setup;
+#[cfg(feature = "tui")]
 mod ui;
+#[cfg(feature = "tui")]
 mod ui_types;
 
 use clap::{arg, command};
 
+use crate::config::{Args, Config, LogLevel};
+#[cfg(feature = "tui")]
 use crate::fetcher::fetcher;
+#[cfg(feature = "tui")]
 use crate::key_handler::key_handler;
-use crate::ui_types::{UiState};
 
 use log::LevelFilter;
 
-#[derive(Deserialize)]
-struct Config {
-    url: String,
-    token: String,
-    client_id: String,
-    log_level: LogLevel,
-    poll_rate: u64,
-}
-
-#[derive(Deserialize)]
-enum LogLevel {
-    Off,
-    Error,
-    Warn,
-    Info,
-    Debug,
-    Trace,
-}
-
-impl Config {
-    pub fn new(args: Args) -> Self {
-        read_toml(args.config_path)
-    }
-}
-
-fn read_toml(config_path: String) -> Config {
-    let config_string = fs::read_to_string(config_path).expect("Could not access the file");
-    let config_toml = toml::from_str(&config_string);
-    match config_toml {
-        Ok(config) => config,
-        Err(e) => panic!("Couldn't access file. Threw {}", e),
-    }
-}
-
-#[derive(Debug, Clone)]
-struct Args {
-    config_path: String,
-}
-
-impl Default for Args {
-    fn default() -> Self {
-        let home_dir = env::var("HOME")
-            .expect("User hasn't defined their HOME variable. Supply a path manually");
-        let env_config_path = format!("{}/.config/haos-rs-client/config.toml", home_dir);
-        info!("{}", env_config_path);
-        Args {
-            config_path: env_config_path,
-        }
-    }
-}
-
 fn main() -> Result<()> {
     let matches = command!()
         .arg(
@@ -80,6 +56,9 @@ fn main() -> Result<()> {
                 .required(false)
                 .default_value(Args::default().config_path.as_str()),
         )
+        .args(cli::args())
+        .subcommands(cli::subcommands())
+        .subcommand(init::command())
         .get_matches();
 
     let rt = tokio::runtime::Builder::new_multi_thread()
@@ -94,7 +73,40 @@ fn main() -> Result<()> {
         ),
     };
 
-    let config = Config::new(args);
+    // Discovery is for finding the url in the first place, so it can't need a config.
+    if let Some(("discover", sub)) = matches.subcommand() {
+        finish(rt.block_on(cli::discover(sub, cli::output_format(&matches))));
+        return Ok(());
+    }
+
+    if let Some(("init", sub)) = matches.subcommand() {
+        if let Err(e) = init::run(&rt, &args.config_path, sub) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // No config yet & about to bring up the UI: ask for the url & token there rather than erroring.
+    #[cfg(feature = "tui")]
+    if matches.subcommand().is_none() && !std::path::Path::new(&args.config_path).exists() {
+        match setup::run(&rt, &args.config_path) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                eprintln!("The setup screen failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let config = match Config::new(args.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
     let log_level: LevelFilter = match config.log_level {
         LogLevel::Off => LevelFilter::Off,
         LogLevel::Error => LevelFilter::Error,
@@ -103,76 +115,209 @@ fn main() -> Result<()> {
         LogLevel::Debug => LevelFilter::Debug,
         LogLevel::Trace => LevelFilter::Trace,
     };
-    simple_logging::log_to_file("log.txt", log_level)
-        .expect("File doesn't exist. This should create the file or smthing I guess.");
+    let log_file = config.log_file();
+    // Not being able to write the log shouldn't stop anything, the UI's log pane still works.
+    if let Err(e) = logging::init(&log_file, log_level, config.log_max_size.saturating_mul(1024)) {
+        eprintln!("warning: couldn't open the log file {}: {}", log_file.display(), e);
+    }
 
-    let haos_conn = HomeAssistantConnection::new(config.url, config.client_id);
+    if config.has_literal_token() && config::is_world_readable(&args.config_path) {
+        let msg = format!(
+            "{} holds a token and can be read by anyone on this machine, chmod it to 600 or use token_file/token_command",
+            args.config_path
+        );
+        warn!("{}", msg);
+        eprintln!("warning: {}", msg);
+    }
+    let (url, token) = match (config.url(), config.token()) {
+        (Ok(url), Ok(token)) => (url, token),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let haos_conn = HomeAssistantConnection::new(url.clone(), config.client_id.clone());
     haos_conn
         .write()
         .expect("Couldn't get the write lock on the token")
-        .set_long_live_token(config.token);
-
-    let locked_state = Arc::new(Mutex::new(UiState::default()));
-    locked_state
-        .lock()
-        .expect("Should be the only person with access to this")
-        .events
-        .1
-        .select(Some(0));
-    locked_state
-        .lock()
-        .expect("Should be the only person with access to this")
-        .services
-        .1
-        .select(Some(0));
-    locked_state
-        .lock()
-        .expect("Should be the only person with access to this")
-        .states
-        .1
-        .select(Some(0));
-
-    let convar = Arc::new(Condvar::new());
-
-    let mut convar_for_painter = Arc::clone(&convar);
-    let mut state_for_painter = Arc::clone(&locked_state);
-
-    let convar_for_fetcher = Arc::clone(&convar);
-    let mut state_for_fetcher = Arc::clone(&locked_state);
-
-    let mut convar_for_keyhandler = Arc::clone(&convar);
-    let mut state_for_keyhandler = Arc::clone(&locked_state);
-    //let key_handler_joiner = rt.spawn(key_handler(&mut locked_state, &mut convar));
-
-    let key_handler_joiner = spawn(move || {
-        rt.block_on(async move {
-            key_handler(&mut state_for_keyhandler, &mut convar_for_keyhandler).await;
-        })
+        .set_long_live_token(token);
+    let tls = TlsConfig {
+        ca_bundle: config.ca_bundle.clone(),
+        accept_invalid_certs_for: config.accept_invalid_certs_for.clone(),
+        client_cert: config.client_cert.clone(),
+        client_key: config.client_key.clone(),
+        proxy: config.proxy.clone(),
+    };
+    if let Err(e) = haos_conn
+        .write()
+        .expect("Couldn't get the write lock on the TLS config")
+        .set_tls_config(tls)
+    {
+        eprintln!("Couldn't set up the connection: {}", e);
+        std::process::exit(1);
+    }
+
+    let replay = matches.get_one::<PathBuf>("replay");
+    if let Some(path) = matches.get_one::<PathBuf>("record") {
+        if let Err(e) = haos_conn.write().expect("Couldn't get the write lock").record_to(path.clone()) {
+            eprintln!("Couldn't record to {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    } else if let Some(path) = replay {
+        if let Err(e) = haos_conn.write().expect("Couldn't get the write lock").replay_from(path) {
+            eprintln!("Couldn't replay {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    // Subcommands do their one thing and leave, no need to bring up the UI.
+    if matches.subcommand().is_some() {
+        let conn = haos_conn.read().expect("Couldn't get the read lock");
+        finish(rt.block_on(cli::run(&conn, &matches)));
+        return Ok(());
+    }
+
+    let code = run_tui(rt, haos_conn, &config, url, replay.is_some());
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Exits the way a subcommand's result says to. Quietly for a closed pipe (IE: `| head`), with its
+/// own code for a `cli::Exit` & with 1 for anything else.
+fn finish(result: std::result::Result<(), Box<dyn std::error::Error>>) {
+    let Err(e) = result else {
+        return;
+    };
+    if let Some(e) = e.downcast_ref::<std::io::Error>() {
+        if e.kind() == std::io::ErrorKind::BrokenPipe {
+            return;
+        }
+    }
+    if let Some(exit) = e.downcast_ref::<cli::Exit>() {
+        if let Some(message) = &exit.message {
+            eprintln!("{}", message);
+        }
+        std::process::exit(exit.code);
+    }
+    eprintln!("{}", e);
+    std::process::exit(1);
+}
+
+/// Brings up the full screen UI and blocks until it's quit, then hands back the code to exit with.
+/// Everything runs on the one runtime: the fetcher on this thread, the UI & the key handler as
+/// blocking tasks since they wait on the terminal. Once the UI closes `shutdown` stops the rest.
+#[cfg(feature = "tui")]
+fn run_tui(
+    rt: tokio::runtime::Runtime,
+    haos_conn: Arc<RwLock<HomeAssistantConnection>>,
+    config: &Config,
+    url: String,
+    replaying: bool,
+) -> i32 {
+    let (action_sender, action_receiver) = std::sync::mpsc::channel();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let shutdown = CancellationToken::new();
+    let give_up = CancellationToken::new();
+
+    // What's replayed has nothing to do with the instance in the config, so it's kept out of its cache.
+    let cache = (!replaying).then(|| cache::CacheFile {
+        path: config.cache_file(),
+        url,
     });
+    let intervals = reducer::intervals(config.poll_rate, &config.refresh);
+    let offline_queue = config
+        .queue_offline
+        .then(|| std::time::Duration::from_secs(config.queue_max_age));
+
+    let code = rt.block_on(async {
+        let key_handler = tokio::task::spawn_blocking({
+            let actions = action_sender.clone();
+            let shutdown = shutdown.clone();
+            move || key_handler(actions, shutdown)
+        });
+        tokio::spawn(watch_signals(action_sender.clone(), shutdown.clone(), give_up.clone()));
+        let ui = tokio::task::spawn_blocking(move || {
+            ui::draw_ui(action_receiver, command_sender, intervals, offline_queue, cache)
+        });
+
+        let closing = async {
+            let quit = ui.await;
+            shutdown.cancel();
+            quit
+        };
+        let fetching = fetcher(haos_conn, command_receiver, action_sender, shutdown.clone(), give_up);
+        let (quit, unfinished) = tokio::join!(closing, fetching);
+        let _ = key_handler.await;
 
-    let fetcher_handler = spawn(move || {
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(async move {
-                fetcher(
-                    &haos_conn,
-                    &convar_for_fetcher,
-                    &mut state_for_fetcher,
-                    config.poll_rate,
-                )
-                .await;
-            })
+        match quit {
+            // The panic hook already put the terminal back & said what went wrong.
+            Err(_) => 101,
+            Ok(_) if unfinished > 0 => {
+                eprintln!("Gave up on {} changes, they might not have gone through", unfinished);
+                1
+            }
+            Ok(quit) => quit.exit_code(),
+        }
     });
+    info!("Exiting with {}", code);
+    code
+}
+
+/// Turns SIGINT & SIGTERM into a quit, so the terminal gets put back the same way as for q. One
+/// that comes in once the UI's closed (IE: a second Ctrl+c) cancels `give_up` instead, so the wait
+/// for the changes still being sent is cut short.
+#[cfg(feature = "tui")]
+async fn watch_signals(
+    actions: std::sync::mpsc::Sender<actions::Action>,
+    shutdown: CancellationToken,
+    give_up: CancellationToken,
+) {
+    loop {
+        let quit = tokio::select! {
+            _ = tokio::signal::ctrl_c() => actions::Quit::Interrupt,
+            _ = terminated() => actions::Quit::Terminate,
+        };
+        if shutdown.is_cancelled() {
+            info!("Got {:?} while closing, not waiting on the changes any longer", quit);
+            give_up.cancel();
+            return;
+        }
+        info!("Got {:?} from a signal", quit);
+        let _ = actions.send(actions::Action::Quit(quit));
+    }
+}
 
-    ui::draw_ui(&mut state_for_painter, &mut convar_for_painter);
+#[cfg(all(feature = "tui", unix))]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            warn!("Couldn't listen for SIGTERM: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
 
-    key_handler_joiner
-        .join()
-        .expect("We were unable to join the key_handler");
-    fetcher_handler
-        .join()
-        .expect("We were unable to join the fetcher");
-    Ok(())
+/// There's no SIGTERM to listen for.
+#[cfg(all(feature = "tui", not(unix)))]
+async fn terminated() {
+    std::future::pending::<()>().await;
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(
+    _rt: tokio::runtime::Runtime,
+    _haos_conn: Arc<RwLock<HomeAssistantConnection>>,
+    _config: &Config,
+    _url: String,
+    _replaying: bool,
+) -> i32 {
+    eprintln!("haoscli was built without the tui feature, give it a subcommand instead (see --help)");
+    2
 }
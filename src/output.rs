@@ -0,0 +1,68 @@
+//! Printing the results of subcommands, either as a table for people or as json/yaml for scripts.
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// What `--output` asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn from_arg(arg: &str) -> Self {
+        match arg {
+            "json" => OutputFormat::Json,
+            "yaml" => OutputFormat::Yaml,
+            _ => OutputFormat::Table,
+        }
+    }
+}
+
+/// A table to print: the header and then one row per item, every row as long as the header.
+pub struct Table {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Prints `value` in the given format. `table` is only built when a table is what's wanted.
+pub fn print<T: Serialize>(format: OutputFormat, value: &T, table: impl FnOnce() -> Table) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, value)?;
+            writeln!(out)
+        }
+        OutputFormat::Yaml => {
+            let yaml = serde_yaml::to_string(value).map_err(io::Error::other)?;
+            write!(out, "{}", yaml)
+        }
+        OutputFormat::Table => write_table(&mut out, &table()),
+    }
+}
+
+/// Lines the columns up, every column is as wide as its widest cell. The last column isn't padded
+/// so long values (IE: attributes) don't drag trailing spaces along.
+fn write_table(out: &mut impl Write, table: &Table) -> io::Result<()> {
+    let mut widths: Vec<usize> = table.header.iter().map(|head| head.len()).collect();
+    for row in &table.rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = table.header.iter().map(|head| head.to_string()).collect();
+    for row in std::iter::once(&header).chain(&table.rows) {
+        let last = row.len().saturating_sub(1);
+        for (idx, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if idx == last {
+                writeln!(out, "{}", cell)?;
+            } else {
+                write!(out, "{:<width$}  ", cell, width = width)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,410 @@
+//! The one place `UiState` changes. Key presses, ticks & whatever the fetcher got back all come in
+//! as `Action`s, and anything that needs sending to Home Assistant goes back out as `Command`s.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use haoscli::types::State;
+
+use crate::actions::{Action, Applied, Change, Command, RequestId, Resource, Response};
+use crate::config::Refresh;
+use crate::key_handler::handle_key;
+use crate::ui_types::{Offline, Pane, PopUpPane, Queued, Request, UiState, Unconfirmed};
+
+/// The statistics only move once an hour or so, no need to hammer the recorder for them.
+const ENERGY_REFRESH: Duration = Duration::from_secs(300);
+/// How long a finished request stays up saying how it went.
+const TOAST_FOR: Duration = Duration::from_secs(5);
+/// How long to wait before the first retry once Home Assistant's gone, doubled each time it's still
+/// not there up to `RECONNECT_AT_MOST`.
+const RECONNECT_FIRST: Duration = Duration::from_secs(1);
+const RECONNECT_AT_MOST: Duration = Duration::from_secs(30);
+/// A fetch that hasn't been answered in this long is sent again. Requests time out well before it,
+/// this is for the websocket, which has nothing of the sort.
+const FETCH_GIVEN_UP: Duration = Duration::from_secs(60);
+
+/// How often each resource is fetched again, from `poll_rate` & the `[refresh]` table in the config.
+pub fn intervals(poll_rate: u64, refresh: &Refresh) -> HashMap<Resource, Duration> {
+    let every = |rate: Option<u64>| Duration::from_millis(rate.unwrap_or(poll_rate));
+    HashMap::from([
+        (Resource::Events, every(refresh.events)),
+        (Resource::Services, every(refresh.services)),
+        (Resource::States, every(refresh.states)),
+        (Resource::Addons, every(refresh.addons)),
+        (Resource::AddonLogs, every(refresh.addon_logs)),
+        (Resource::Backups, every(refresh.backups)),
+        (Resource::Energy, refresh.energy.map_or(ENERGY_REFRESH, Duration::from_millis)),
+    ])
+}
+
+pub fn reduce(state: &mut UiState, action: Action) -> Vec<Command> {
+    state.requests.retain(|request| match &request.outcome {
+        Some((finished, _)) => finished.elapsed() < TOAST_FOR,
+        None => true,
+    });
+    let mut commands = match action {
+        Action::Key(key) => {
+            let before = (state.active.clone(), state.energy_period);
+            let mut commands = handle_key(state, key);
+            // A new pane (or energy period) shouldn't have to wait for the next tick to fill in.
+            if (state.active.clone(), state.energy_period) != before && state.active != Pane::None {
+                commands.extend(poll(state));
+            }
+            commands
+        }
+        Action::Resize => Vec::new(),
+        Action::Quit(quit) => {
+            state.quit(quit);
+            Vec::new()
+        }
+        Action::Tick => poll(state),
+        Action::Response(response) => {
+            let mut asked = None;
+            if let Some(resource) = response.resource() {
+                asked = state.fetching.remove(&resource);
+                state.fetched.insert(resource, Instant::now());
+            }
+            let mut commands = track_connection(state, &response);
+            commands.extend(apply(state, response, asked));
+            commands
+        }
+    };
+    // Anything still waiting on an answer isn't asked for again, unless it's waited so long it's
+    // never going to get one.
+    commands.retain(|command| match command.resource() {
+        Some(resource) => match state.fetching.entry(resource) {
+            Entry::Occupied(entry) if entry.get().elapsed() < FETCH_GIVEN_UP => false,
+            Entry::Occupied(mut entry) => {
+                warn!("Never heard back about {:?}, asking again", entry.key());
+                entry.insert(Instant::now());
+                true
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+        },
+        None => true,
+    });
+    commands
+}
+
+/// What's due to be fetched again for the pane that's showing. Nothing is while a pop up is being
+/// typed into, & while offline it's just the states once it's time to retry.
+fn poll(state: &UiState) -> Vec<Command> {
+    if let Some(offline) = &state.offline {
+        return match Instant::now() >= offline.retry_at {
+            true => vec![Command::FetchStates],
+            false => Vec::new(),
+        };
+    }
+    if state.editing() {
+        return Vec::new();
+    }
+    fetches(state, |resource| match (state.fetched.get(&resource), state.intervals.get(&resource)) {
+        (Some(at), Some(interval)) => at.elapsed() >= *interval,
+        _ => true,
+    })
+}
+
+/// Everything the pane that's showing needs, however recently it was fetched & even while editing.
+pub fn refresh(state: &UiState) -> Vec<Command> {
+    info!("Refreshing {:?}", state.active);
+    fetches(state, |_| true)
+}
+
+/// The events, services & states are always kept up to date, the rest only while someone's looking
+/// at them since plenty of installs don't have a supervisor at all.
+fn fetches(state: &UiState, due: impl Fn(Resource) -> bool) -> Vec<Command> {
+    let mut commands = vec![Command::FetchEvents, Command::FetchServices, Command::FetchStates];
+    match state.active {
+        Pane::Addons => commands.push(Command::FetchAddons),
+        Pane::PopUp(PopUpPane::Addons) => {
+            commands.push(Command::FetchAddons);
+            if let Some(slug) = selected_addon(state) {
+                commands.push(Command::FetchAddonLogs(slug));
+            }
+        }
+        Pane::Backups | Pane::PopUp(PopUpPane::Backups) => commands.push(Command::FetchBackups),
+        _ => (),
+    }
+    commands.retain(|command| command.resource().is_none_or(&due));
+
+    // A different period can't wait for the statistics to be due.
+    if state.active == Pane::Energy && (due(Resource::Energy) || state.energy_fetched != Some(state.energy_period)) {
+        let names: HashMap<String, String> = state
+            .states
+            .0
+            .iter()
+            .filter_map(|state| {
+                let name = state.attributes.get("friendly_name")?.as_str()?;
+                Some((state.entity_id.clone(), name.to_string()))
+            })
+            .collect();
+        commands.push(Command::FetchEnergy(state.energy_period, names));
+    }
+    commands
+}
+
+/// Notices Home Assistant going away & coming back. Once it's back everything on screen is fetched
+/// again & the websocket is opened afresh, the old one went with the server.
+fn track_connection(state: &mut UiState, response: &Response) -> Vec<Command> {
+    // Anything else, even an error, means it answered.
+    let unreachable = response.error().filter(|e| e.is_connection_error());
+    match (unreachable, &mut state.offline) {
+        (Some(e), None) => {
+            warn!("Lost the connection to Home Assistant, retrying in {:?}: {}", backoff(0), e);
+            state.offline = Some(Offline {
+                since: Instant::now(),
+                attempt: 0,
+                retry_at: Instant::now() + backoff(0),
+                error: e.to_string(),
+            });
+            Vec::new()
+        }
+        // Answers to what was sent before the retry was due don't count as another attempt.
+        (Some(e), Some(offline)) if Instant::now() >= offline.retry_at => {
+            offline.attempt += 1;
+            let wait = backoff(offline.attempt);
+            debug!("Still can't reach Home Assistant, retrying in {:?}: {}", wait, e);
+            offline.retry_at = Instant::now() + wait;
+            offline.error = e.to_string();
+            Vec::new()
+        }
+        (None, Some(offline)) => {
+            info!("Home Assistant is back after {:?}", offline.since.elapsed());
+            state.offline = None;
+            let mut commands = vec![Command::ResetWebSocket];
+            commands.extend(refresh(state));
+            commands.extend(replay_queue(state));
+            commands
+        }
+        (Some(_), Some(_)) | (None, None) => Vec::new(),
+    }
+}
+
+/// How long to wait before retrying after `attempt` retries have already failed.
+fn backoff(attempt: u32) -> Duration {
+    RECONNECT_FIRST
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RECONNECT_AT_MOST)
+}
+
+/// `asked` is when the poll being answered was sent.
+fn apply(state: &mut UiState, response: Response, asked: Option<Instant>) -> Vec<Command> {
+    match response {
+        Response::Events(Ok(events)) => {
+            state.events.0 = events;
+            state.events.1.select(clamp(state.events.1.selected(), state.events.0.len()));
+            state.stale.remove(&Resource::Events);
+        }
+        Response::Events(Err(e)) => warn!("Couldn't get the events: {}", e),
+        Response::Services(Ok(services)) => {
+            state.services.0 = services;
+            state.services.1.select(clamp(state.services.1.selected(), state.services.0.len()));
+            state.stale.remove(&Resource::Services);
+        }
+        Response::Services(Err(e)) => warn!("Couldn't get the services: {}", e),
+        Response::States(Ok(states)) => {
+            state.states.0 = states;
+            state.states.1.select(clamp(state.states.1.selected(), state.states.0.len()));
+            state.stale.remove(&Resource::States);
+            settle_unconfirmed(state, asked.unwrap_or_else(Instant::now));
+        }
+        Response::States(Err(e)) => warn!("Couldn't get the states: {}", e),
+        Response::Addons(Ok(addons)) => {
+            state.addons.0 = addons;
+            state.addons.1.select(clamp(state.addons.1.selected(), state.addons.0.len()));
+        }
+        Response::Addons(Err(e)) => {
+            warn!("Couldn't get the add-ons from the supervisor: {}", e);
+            state.addon_status = e.to_string();
+        }
+        Response::AddonLogs(slug, Ok(logs)) => {
+            // The selection could've moved on while these were on their way.
+            if state.active == Pane::PopUp(PopUpPane::Addons) && selected_addon(state).as_ref() == Some(&slug) {
+                state.addon_logs.0 = logs;
+            }
+        }
+        Response::AddonLogs(slug, Err(e)) => warn!("Couldn't get the logs for {}: {}", slug, e),
+        Response::Backups(Ok(backups)) => {
+            state.backups.0 = backups;
+            state.backups.1.select(clamp(state.backups.1.selected(), state.backups.0.len()));
+        }
+        Response::Backups(Err(e)) => {
+            warn!("Couldn't get the backups from the supervisor: {}", e);
+            state.backup_status = e.to_string();
+        }
+        Response::Energy(period, Ok(summary)) => {
+            state.energy = summary;
+            state.energy_status = String::new();
+            state.energy_fetched = Some(period);
+        }
+        Response::Energy(_, Err(e)) => {
+            warn!("Couldn't get the energy statistics: {}", e);
+            state.energy_status = e.to_string();
+        }
+        Response::Sent(id, result) => return finish_request(state, id, result),
+    }
+    Vec::new()
+}
+
+/// Keeps a selection inside a list that's just been replaced, a refresh can shrink it out from
+/// under whatever was selected (or a pop up that's open over it).
+fn clamp(selected: Option<usize>, len: usize) -> Option<usize> {
+    selected.filter(|_| len > 0).map(|idx| idx.min(len - 1))
+}
+
+/// Sends what was queued while offline, oldest first. Anything that's been waiting longer than the
+/// config allows is dropped instead, it's likely not wanted any more, IE: turning the lights on for
+/// an evening that's over.
+fn replay_queue(state: &mut UiState) -> Vec<Command> {
+    let Some(max_age) = state.offline_queue else {
+        return Vec::new();
+    };
+    let mut changes = Vec::new();
+    for queued in std::mem::take(&mut state.queue.0) {
+        let mut request = Request::new(queued.id, queued.change.clone());
+        request.queued = Some(queued.queued);
+        if queued.queued.elapsed() > max_age {
+            warn!("Dropped {}, it was queued {:?} ago", request.description, queued.queued.elapsed());
+            let why = format!("{} was dropped, it was queued {}s ago", request.description, queued.queued.elapsed().as_secs());
+            request.outcome = Some((Instant::now(), Err(why)));
+        } else {
+            changes.push((queued.id, queued.change));
+        }
+        state.requests.push(request);
+    }
+    match changes.is_empty() {
+        true => Vec::new(),
+        false => {
+            info!("Sending what was queued while offline, {} change(s)", changes.len());
+            vec![Command::Replay(changes)]
+        }
+    }
+}
+
+/// Marks the request as done, which turns its spinner into a toast, and fetches whatever it changed
+/// again straight away so the change shows up. One that never got through because the connection
+/// went is queued again, if that's turned on.
+fn finish_request(state: &mut UiState, id: RequestId, result: haoscli::error::Result<Applied>) -> Vec<Command> {
+    let Some(idx) = state.requests.iter().position(|request| request.id == id) else {
+        return Vec::new();
+    };
+    if let Err(e) = &result {
+        if e.is_connection_error() && state.offline_queue.is_some() {
+            let request = state.requests.remove(idx);
+            info!("Queued {} again, it didn't get through: {}", request.description, e);
+            let queued = request.queued.unwrap_or(request.started);
+            // Back where it was, the replay's answers can come back in any order.
+            let at = state.queue.0.partition_point(|earlier| earlier.queued <= queued);
+            state.queue.0.insert(
+                at,
+                Queued {
+                    id,
+                    change: request.change,
+                    queued,
+                },
+            );
+            return Vec::new();
+        }
+    }
+    let request = &state.requests[idx];
+    let outcome = match result {
+        Ok(applied) => {
+            info!("{}: {}", request.description, applied.summary);
+            let change = request.change.clone();
+            show_unconfirmed(state, applied.changed, change);
+            Ok(applied.summary)
+        }
+        Err(e) => {
+            warn!("{} failed: {}", request.description, e);
+            Err(format!("{} failed: {}", request.description, e))
+        }
+    };
+    let status = match &outcome {
+        Ok(answer) => answer.clone(),
+        Err(e) => e.clone(),
+    };
+    let request = &mut state.requests[idx];
+    request.outcome = Some((Instant::now(), outcome));
+    match request.change.resource() {
+        Resource::Addons => {
+            state.addon_status = status;
+            vec![Command::FetchAddons]
+        }
+        Resource::Backups => {
+            state.backup_status = status;
+            vec![Command::FetchBackups]
+        }
+        _ => vec![Command::FetchStates],
+    }
+}
+
+/// Puts what the change said it did into the states straight away rather than waiting on the next
+/// fetch, which is still what has the final say.
+fn show_unconfirmed(state: &mut UiState, changed: Vec<State>, change: Change) {
+    for expected in changed {
+        let shown = state.states.0.iter_mut().find(|state| state.entity_id == expected.entity_id);
+        // A second change before the first is confirmed is still shown over the same fetch.
+        let fetched = match state.unconfirmed.remove(&expected.entity_id) {
+            Some(earlier) => earlier.fetched,
+            None => shown.as_deref().cloned(),
+        };
+        match shown {
+            Some(shown) => *shown = expected.clone(),
+            None => state.states.0.push(expected.clone()),
+        }
+        let unconfirmed = Unconfirmed {
+            expected,
+            fetched,
+            since: Instant::now(),
+            change: change.clone(),
+        };
+        state.unconfirmed.insert(unconfirmed.expected.entity_id.clone(), unconfirmed);
+    }
+}
+
+/// Checks the unconfirmed states against the ones just fetched. A fetch sent before the change was
+/// answered can't know about it, so the change is shown over it until a later one comes in. If a
+/// later one disagrees it wins, & says so in a toast.
+fn settle_unconfirmed(state: &mut UiState, asked: Instant) {
+    for (entity_id, mut unconfirmed) in std::mem::take(&mut state.unconfirmed) {
+        let fetched = state.states.0.iter_mut().find(|state| state.entity_id == entity_id);
+        if asked < unconfirmed.since {
+            unconfirmed.fetched = fetched.as_deref().cloned();
+            match fetched {
+                Some(fetched) => *fetched = unconfirmed.expected.clone(),
+                None => state.states.0.push(unconfirmed.expected.clone()),
+            }
+            state.unconfirmed.insert(entity_id, unconfirmed);
+            continue;
+        }
+        let expected = &unconfirmed.expected.state;
+        let actual = fetched.map(|fetched| fetched.state.clone());
+        if actual.as_ref() == Some(expected) {
+            debug!("{} is confirmed as {}", entity_id, expected);
+            continue;
+        }
+        let notice = match actual {
+            Some(actual) => format!("{} is {} after all, not {}", entity_id, actual, expected),
+            None => format!("{} is gone, it didn't stay {}", entity_id, expected),
+        };
+        warn!("{}", notice);
+        state.notify(unconfirmed.change, Err(notice));
+    }
+}
+
+fn selected_addon(state: &UiState) -> Option<String> {
+    state
+        .addons
+        .1
+        .selected()
+        .and_then(|idx| state.addons.0.get(idx))
+        .map(|addon| addon.slug.clone())
+}
+
+#[cfg(test)]
+mod tests;
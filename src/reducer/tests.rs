@@ -0,0 +1,263 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use haoscli::error::Error;
+use haoscli::types::{AddonAction, State};
+
+use super::{apply, backoff, finish_request, reduce, replay_queue, track_connection};
+use crate::actions::{Action, Applied, Change, Command, Quit, Response};
+use crate::ui_types::{Pane, Queued, Request, UiState};
+
+fn queued_ago(state: &mut UiState, id: u64, ago: Duration) -> Instant {
+    let queued = Instant::now().checked_sub(ago).unwrap();
+    state.queue.0.push(Queued {
+        id,
+        change: Change::Addon(String::from("core_mosquitto"), AddonAction::Restart),
+        queued,
+    });
+    queued
+}
+
+fn connection_lost() -> Error {
+    Error::Status(reqwest::StatusCode::BAD_GATEWAY, String::new())
+}
+
+#[test]
+fn requeued_changes_keep_their_age() {
+    let mut state = UiState {
+        offline_queue: Some(Duration::from_secs(300)),
+        ..Default::default()
+    };
+    let queued = queued_ago(&mut state, 1, Duration::from_secs(100));
+
+    assert!(matches!(replay_queue(&mut state).as_slice(), [Command::Replay(changes)] if changes.len() == 1));
+    assert!(finish_request(&mut state, 1, Err(connection_lost())).is_empty());
+
+    assert_eq!(state.queue.0.len(), 1);
+    assert_eq!(state.queue.0[0].queued, queued);
+}
+
+#[test]
+fn requeued_changes_keep_their_place() {
+    let mut state = UiState {
+        offline_queue: Some(Duration::from_secs(300)),
+        ..Default::default()
+    };
+    queued_ago(&mut state, 1, Duration::from_secs(100));
+    queued_ago(&mut state, 2, Duration::from_secs(50));
+    replay_queue(&mut state);
+    // Queued while the replay was on its way.
+    queued_ago(&mut state, 3, Duration::ZERO);
+
+    finish_request(&mut state, 2, Err(connection_lost()));
+    finish_request(&mut state, 1, Err(connection_lost()));
+    let ids: Vec<u64> = state.queue.0.iter().map(|queued| queued.id).collect();
+    assert_eq!(ids, [1, 2, 3]);
+}
+
+#[test]
+fn requeued_changes_still_expire() {
+    let mut state = UiState {
+        offline_queue: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    queued_ago(&mut state, 1, Duration::from_secs(50));
+    replay_queue(&mut state);
+    finish_request(&mut state, 1, Err(connection_lost()));
+
+    // Still queued, but back-dated past the limit it's dropped rather than sent next time.
+    state.queue.0[0].queued = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+    assert!(replay_queue(&mut state).is_empty());
+    assert!(state.queue.0.is_empty());
+    assert!(matches!(state.requests.last().and_then(|r| r.outcome.as_ref()), Some((_, Err(_)))));
+}
+
+fn states(ids: &[&str]) -> Vec<State> {
+    let states = ids
+        .iter()
+        .map(|id| serde_json::json!({"entity_id": id, "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}}))
+        .collect();
+    serde_json::from_value(serde_json::Value::Array(states)).unwrap()
+}
+
+#[test]
+fn refreshes_keep_the_selection_in_the_list() {
+    let mut state = UiState::default();
+    state.states.0 = states(&["light.desk", "light.hall", "light.porch"]);
+    state.states.1.select(Some(2));
+
+    apply(&mut state, Response::States(Ok(states(&["light.desk", "light.hall"]))), None);
+    assert_eq!(state.states.1.selected(), Some(1));
+
+    apply(&mut state, Response::States(Ok(Vec::new())), None);
+    assert_eq!(state.states.1.selected(), None);
+}
+
+fn press(state: &mut UiState, code: KeyCode, modifiers: KeyModifiers) {
+    reduce(state, Action::Key(KeyEvent::new(code, modifiers)));
+}
+
+#[test]
+fn quitting_asks_before_dropping_the_queue() {
+    let mut state = UiState {
+        active: Pane::States,
+        ..Default::default()
+    };
+    queued_ago(&mut state, 1, Duration::from_secs(10));
+
+    press(&mut state, KeyCode::Char('q'), KeyModifiers::NONE);
+    assert!(state.quit_armed);
+    assert_eq!(state.active, Pane::States);
+    // Anything else and it's forgotten about.
+    press(&mut state, KeyCode::Down, KeyModifiers::NONE);
+    assert!(!state.quit_armed);
+
+    press(&mut state, KeyCode::Char('c'), KeyModifiers::CONTROL);
+    press(&mut state, KeyCode::Char('q'), KeyModifiers::NONE);
+    assert_eq!((state.active, state.quitting), (Pane::None, Some(Quit::Key)));
+}
+
+#[test]
+fn quitting_with_nothing_queued_doesnt_ask() {
+    let mut state = UiState {
+        active: Pane::States,
+        ..Default::default()
+    };
+    press(&mut state, KeyCode::Char('c'), KeyModifiers::CONTROL);
+    assert_eq!((state.active, state.quitting), (Pane::None, Some(Quit::Interrupt)));
+}
+
+fn turned_off(state: &mut UiState) -> Instant {
+    state.states.0 = states(&["light.desk", "light.hall"]);
+    let change = Change::CallService {
+        domain: String::from("light"),
+        service: String::from("turn_off"),
+        entity_id: String::from("light.desk"),
+    };
+    state.requests.push(Request::new(1, change));
+    let asked = Instant::now().checked_sub(Duration::from_millis(1)).unwrap();
+    let mut off = states(&["light.desk"]).remove(0);
+    off.state = String::from("off");
+    let applied = Applied {
+        summary: String::from("light.desk is now off"),
+        changed: vec![off],
+    };
+    assert!(matches!(finish_request(state, 1, Ok(applied)).as_slice(), [Command::FetchStates]));
+    asked
+}
+
+fn shown(state: &UiState, entity_id: &str) -> String {
+    state.states.0.iter().find(|state| state.entity_id == entity_id).unwrap().state.clone()
+}
+
+#[test]
+fn changes_show_until_a_fetch_confirms_them() {
+    let mut state = UiState::default();
+    let asked = turned_off(&mut state);
+    assert_eq!(shown(&state, "light.desk"), "off");
+    assert!(state.unconfirmed.contains_key("light.desk"));
+
+    // Sent before the change was answered, so it can't know about it.
+    apply(&mut state, Response::States(Ok(states(&["light.desk", "light.hall"]))), Some(asked));
+    assert_eq!(shown(&state, "light.desk"), "off");
+    assert!(state.unconfirmed.contains_key("light.desk"));
+
+    let mut fetched = states(&["light.desk", "light.hall"]);
+    fetched[0].state = String::from("off");
+    apply(&mut state, Response::States(Ok(fetched)), Some(Instant::now()));
+    assert_eq!(shown(&state, "light.desk"), "off");
+    assert!(state.unconfirmed.is_empty());
+    assert_eq!(state.requests.len(), 1);
+}
+
+#[test]
+fn fetches_that_disagree_roll_changes_back() {
+    let mut state = UiState::default();
+    turned_off(&mut state);
+
+    apply(&mut state, Response::States(Ok(states(&["light.desk", "light.hall"]))), Some(Instant::now()));
+    assert_eq!(shown(&state, "light.desk"), "on");
+    assert!(state.unconfirmed.is_empty());
+    assert!(matches!(
+        state.requests.last().and_then(|r| r.outcome.as_ref()),
+        Some((_, Err(notice))) if notice == "light.desk is on after all, not off"
+    ));
+}
+
+fn confirmed(state: &UiState) -> Vec<(String, String)> {
+    state
+        .confirmed_states()
+        .into_iter()
+        .map(|state| (state.entity_id, state.state))
+        .collect()
+}
+
+#[test]
+fn only_confirmed_states_are_cached() {
+    let pair = |entity_id: &str, state: &str| (entity_id.to_string(), state.to_string());
+    let mut state = UiState::default();
+    let asked = turned_off(&mut state);
+    assert_eq!(confirmed(&state), [pair("light.desk", "on"), pair("light.hall", "on")]);
+
+    // Fetches from before the change still say what's cached underneath it.
+    apply(&mut state, Response::States(Ok(states(&["light.hall"]))), Some(asked));
+    assert_eq!(shown(&state, "light.desk"), "off");
+    assert_eq!(confirmed(&state), [pair("light.hall", "on")]);
+    apply(&mut state, Response::States(Ok(states(&["light.desk", "light.hall"]))), Some(asked));
+    assert_eq!(confirmed(&state), [pair("light.desk", "on"), pair("light.hall", "on")]);
+
+    let mut fetched = states(&["light.desk", "light.hall"]);
+    fetched[0].state = String::from("off");
+    apply(&mut state, Response::States(Ok(fetched)), Some(Instant::now()));
+    assert_eq!(confirmed(&state), [pair("light.desk", "off"), pair("light.hall", "on")]);
+}
+
+#[test]
+fn retries_back_off() {
+    let waits: Vec<u64> = (0..7).map(|attempt| backoff(attempt).as_secs()).collect();
+    assert_eq!(waits, [1, 2, 4, 8, 16, 30, 30]);
+    assert_eq!(backoff(u32::MAX), Duration::from_secs(30));
+}
+
+#[test]
+fn goes_offline_and_comes_back() {
+    let mut state = UiState {
+        offline_queue: Some(Duration::from_secs(300)),
+        ..Default::default()
+    };
+    let lost = Response::States(Err(connection_lost()));
+
+    assert!(track_connection(&mut state, &lost).is_empty());
+    let offline = state.offline.as_ref().unwrap();
+    assert_eq!(offline.attempt, 0);
+    assert!(offline.retry_at <= Instant::now() + backoff(0));
+    // Answers to what was already on its way aren't another attempt.
+    track_connection(&mut state, &lost);
+    assert_eq!(state.offline.as_ref().unwrap().attempt, 0);
+
+    let offline = state.offline.as_mut().unwrap();
+    offline.retry_at = Instant::now().checked_sub(Duration::from_millis(1)).unwrap();
+    track_connection(&mut state, &lost);
+    let offline = state.offline.as_ref().unwrap();
+    assert_eq!(offline.attempt, 1);
+    assert!(offline.retry_at > Instant::now() + backoff(0));
+    assert!(offline.retry_at <= Instant::now() + backoff(1));
+
+    queued_ago(&mut state, 1, Duration::from_secs(10));
+    let commands = track_connection(&mut state, &Response::States(Ok(Vec::new())));
+    assert!(state.offline.is_none());
+    assert!(matches!(commands.first(), Some(Command::ResetWebSocket)));
+    assert!(commands.iter().any(|command| matches!(command, Command::Replay(changes) if changes.len() == 1)));
+}
+
+#[test]
+fn any_answer_means_its_back() {
+    let mut state = UiState::default();
+    track_connection(&mut state, &Response::States(Err(connection_lost())));
+    assert!(state.offline.is_some());
+
+    let not_found = Error::Status(reqwest::StatusCode::NOT_FOUND, String::new());
+    assert!(!track_connection(&mut state, &Response::States(Err(not_found))).is_empty());
+    assert!(state.offline.is_none());
+}
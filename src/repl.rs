@@ -0,0 +1,316 @@
+//! `haoscli repl`: a line at a time shell taking the same commands as the CLI, with history and tab
+//! completion of entity ids, services & their fields. Nicer than the TUI over a laggy ssh link.
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use clap::Command;
+use log::{info, warn};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use haoscli::types::HomeAssistantConnection;
+
+use crate::cli::{self, Exit};
+
+const PROMPT: &str = "haos> ";
+
+type CommandRun<'a> = Pin<Box<dyn Future<Output = Result<(), Box<dyn Error>>> + 'a>>;
+
+/// Everything tab completion knows about, fetched when the repl starts & on `refresh`.
+struct ReplHelper {
+    command: Command<'static>,
+    entities: Vec<String>,
+    /// `domain.service` to the names of its fields.
+    services: BTreeMap<String, Vec<String>>,
+}
+
+pub async fn run(haos_conn: &HomeAssistantConnection) -> Result<(), Box<dyn Error>> {
+    let command = repl_command();
+    let mut editor = Editor::<ReplHelper>::new()?;
+    editor.set_helper(Some(ReplHelper {
+        command: command.clone(),
+        entities: Vec::new(),
+        services: BTreeMap::new(),
+    }));
+    refresh(haos_conn, &mut editor).await;
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // There's no history the first time round, that's fine.
+        let _ = editor.load_history(path);
+    }
+    println!("Type help for the commands, refresh to reload the completions, exit or Ctrl+d to leave.");
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        let words = match split_line(line) {
+            Ok(Some(words)) => words,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        match words[0].as_str() {
+            "exit" | "quit" => break,
+            "refresh" => {
+                refresh(haos_conn, &mut editor).await;
+                continue;
+            }
+            _ => (),
+        }
+
+        let matches = match command.clone().try_get_matches_from(&words) {
+            Ok(matches) => matches,
+            Err(e) => {
+                // Covers `help` & `--help` too, which clap hands back as an "error".
+                let _ = e.print();
+                continue;
+            }
+        };
+        // Boxed since `cli::run` is what called us, and Ctrl+c stops the command rather than the
+        // whole repl.
+        let command_run: CommandRun = Box::pin(cli::run(haos_conn, &matches));
+        let result = tokio::select! {
+            result = command_run => result,
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Interrupted");
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            match e.downcast_ref::<Exit>() {
+                Some(exit) => {
+                    if let Some(message) = &exit.message {
+                        eprintln!("{}", message);
+                    }
+                    eprintln!("(exit {})", exit.code);
+                }
+                None => eprintln!("{}", e),
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            warn!("Couldn't save the repl history to {}: {}", path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Splits a line up the way a shell would, `None` when there's nothing to run. A line that's only
+/// a comment, IE: `# note`, splits into no words at all.
+fn split_line(line: &str) -> Result<Option<Vec<String>>, shell_words::ParseError> {
+    let words = shell_words::split(line)?;
+    Ok((!words.is_empty()).then_some(words))
+}
+
+/// The same subcommands as the CLI, minus `repl` itself, read without a binary name.
+fn repl_command() -> Command<'static> {
+    Command::new("haos")
+        .no_binary_name(true)
+        .subcommand_required(true)
+        .args(cli::args())
+        .subcommands(
+            cli::subcommands()
+                .into_iter()
+                .filter(|command| command.get_name() != "repl"),
+        )
+        .subcommand(Command::new("refresh").about("Reload the entities & services used for tab completion"))
+        .subcommand(Command::new("exit").about("Leave the repl, Ctrl+d works too"))
+}
+
+/// Reloads what tab completion offers. Failing here isn't fatal, completion just knows less.
+async fn refresh(haos_conn: &HomeAssistantConnection, editor: &mut Editor<ReplHelper>) {
+    let Some(helper) = editor.helper_mut() else {
+        return;
+    };
+    match haos_conn.get_states().await {
+        Ok(states) => helper.entities = states.into_iter().map(|state| state.entity_id).collect(),
+        Err(e) => eprintln!("Couldn't load the entities for completion: {}", e),
+    }
+    match haos_conn.get_services().await {
+        Ok(services) => {
+            helper.services.clear();
+            for service in services {
+                let Some(map) = service.services.as_object() else {
+                    continue;
+                };
+                for (name, details) in map {
+                    let fields = details
+                        .get("fields")
+                        .and_then(|fields| fields.as_object())
+                        .map(|fields| fields.keys().cloned().collect())
+                        .unwrap_or_default();
+                    helper.services.insert(format!("{}.{}", service.domain, name), fields);
+                }
+            }
+        }
+        Err(e) => eprintln!("Couldn't load the services for completion: {}", e),
+    }
+    info!(
+        "repl completion has {} entities & {} services",
+        helper.entities.len(),
+        helper.services.len()
+    );
+}
+
+/// `$XDG_DATA_HOME/haoscli/repl_history`, falling back to `~/.local/share`.
+fn history_path() -> Option<PathBuf> {
+    let data_dir = match env::var("XDG_DATA_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".local/share"),
+    };
+    let dir = data_dir.join("haoscli");
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        warn!("Couldn't create {}, not keeping repl history: {}", dir.display(), e);
+        return None;
+    }
+    Some(dir.join("repl_history"))
+}
+
+impl ReplHelper {
+    /// What could go where `word` is, given the whole words before it.
+    fn candidates(&self, words: &[&str], word: &str) -> Vec<String> {
+        // Walk down the subcommands that have been typed so far.
+        let mut command = &self.command;
+        let mut rest = words;
+        while let Some((first, tail)) = rest.split_first() {
+            match command.find_subcommand(*first) {
+                Some(sub) => {
+                    command = sub;
+                    rest = tail;
+                }
+                None => break,
+            }
+        }
+
+        if word.starts_with('-') {
+            return command
+                .get_arguments()
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{}", long))
+                .collect();
+        }
+        if command.has_subcommands() {
+            return command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+        }
+
+        // The value for a flag, IE: `--target <tab>`.
+        if let Some(flag) = rest.last().and_then(|last| last.strip_prefix("--")) {
+            let takes_value = command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(flag) && arg.is_takes_value_set());
+            if takes_value {
+                return match flag {
+                    "domain" => self.domains(),
+                    "target" | "entity" => self.entities.clone(),
+                    _ => Vec::new(),
+                };
+            }
+        }
+
+        let positionals = positionals(command, rest);
+        match (command.get_name(), positionals.first()) {
+            ("call", None) => self.services.keys().cloned().collect(),
+            ("call", Some(service)) => self
+                .services
+                .get(*service)
+                .map(|fields| fields.iter().map(|field| format!("{}=", field)).collect())
+                .unwrap_or_default(),
+            _ => self.entities.clone(),
+        }
+    }
+
+    fn domains(&self) -> Vec<String> {
+        let mut domains: Vec<String> = self
+            .entities
+            .iter()
+            .filter_map(|entity| entity.split_once('.').map(|(domain, _)| domain.to_string()))
+            .collect();
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+}
+
+/// The words that aren't flags or the values that go with them.
+fn positionals<'a>(command: &Command<'static>, words: &[&'a str]) -> Vec<&'a str> {
+    let mut positionals = Vec::new();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        match word.strip_prefix("--") {
+            Some(flag) => {
+                let takes_value = command
+                    .get_arguments()
+                    .any(|arg| arg.get_long() == Some(flag) && arg.is_takes_value_set());
+                if takes_value {
+                    words.next();
+                }
+            }
+            None => positionals.push(*word),
+        }
+    }
+    positionals
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map(|idx| idx + 1).unwrap_or(0);
+        let word = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let mut candidates: Vec<Pair> = self
+            .candidates(&words, word)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                // `field=` wants its value typed right after it, everything else gets a space.
+                replacement: match candidate.ends_with('=') {
+                    true => candidate,
+                    false => format!("{} ", candidate),
+                },
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.display == b.display);
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests;
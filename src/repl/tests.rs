@@ -0,0 +1,22 @@
+use super::split_line;
+
+#[test]
+fn nothing_to_run() {
+    for line in ["", "   ", "# note", "  # note with \"quotes\""] {
+        assert_eq!(split_line(line), Ok(None), "{:?}", line);
+    }
+}
+
+#[test]
+fn words_like_a_shell() {
+    assert_eq!(
+        split_line(r#"call light.turn_on --data '{"brightness": 120}' # dim it"#),
+        Ok(Some(vec![
+            String::from("call"),
+            String::from("light.turn_on"),
+            String::from("--data"),
+            String::from(r#"{"brightness": 120}"#),
+        ]))
+    );
+    assert!(split_line("states get 'light.desk").is_err());
+}
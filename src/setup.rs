@@ -0,0 +1,330 @@
+//! The first run screen. When there's no config file yet this asks for the url & token, lets the
+//! connection be tested and writes the config before the normal UI comes up.
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use tui::{Frame, Terminal};
+
+use haoscli::discovery::{discover, DiscoveredInstance};
+use haoscli::types::HomeAssistantConnection;
+
+use crate::config::check_url;
+use crate::init::{write_config, DEFAULT_URL};
+use crate::ui::restore_on_panic;
+
+/// How long Ctrl+f listens for instances on the LAN.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Url,
+    Token,
+}
+
+/// What the last thing we tried said.
+enum Status {
+    Info(String),
+    Good(String),
+    Bad(String),
+}
+
+/// What a key asked for that `on_key` can't do itself.
+#[derive(Debug, PartialEq, Eq)]
+enum Next {
+    Nothing,
+    Quit,
+    Test,
+    Save,
+    TestAndSave,
+    Find,
+}
+
+struct SetupState {
+    url: String,
+    token: String,
+    focus: Field,
+    status: Status,
+    /// What Ctrl+f turned up & which of them is in the url box.
+    found: (Vec<DiscoveredInstance>, ListState),
+}
+
+impl SetupState {
+    fn new(config_path: &str) -> Self {
+        SetupState {
+            url: String::from(DEFAULT_URL),
+            token: String::new(),
+            focus: Field::Url,
+            status: Status::Info(format!(
+                "No config at {} yet, let's make one. Ctrl+f looks for Home Assistant on your network.",
+                config_path
+            )),
+            found: (Vec::new(), ListState::default()),
+        }
+    }
+}
+
+/// Brings up the setup screen. Gives back `true` once the config is written, `false` if the user
+/// backed out with Esc.
+pub fn run(rt: &tokio::runtime::Runtime, config_path: &str) -> io::Result<bool> {
+    enable_raw_mode()?;
+    let mut std_out = io::stdout();
+    execute!(std_out, EnterAlternateScreen)?;
+    restore_on_panic();
+    let mut terminal = Terminal::new(CrosstermBackend::new(std_out))?;
+
+    let result = setup_loop(&mut terminal, rt, config_path);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn setup_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rt: &tokio::runtime::Runtime,
+    config_path: &str,
+) -> io::Result<bool> {
+    let mut state = SetupState::new(config_path);
+
+    loop {
+        terminal.draw(|f| draw_setup(f, &state))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let Event::Key(KeyEvent { code, modifiers, .. }) = event::read()? else {
+            continue;
+        };
+        match on_key(&mut state, code, modifiers) {
+            Next::Nothing => (),
+            Next::Quit => return Ok(false),
+            Next::Test => {
+                show_testing(terminal, &mut state)?;
+                state.status = test_connection(rt, &state.url, &state.token);
+            }
+            Next::Save => {
+                if save(&mut state, config_path) {
+                    return Ok(true);
+                }
+            }
+            Next::TestAndSave => {
+                show_testing(terminal, &mut state)?;
+                state.status = test_connection(rt, &state.url, &state.token);
+                if matches!(state.status, Status::Good(_)) && save(&mut state, config_path) {
+                    return Ok(true);
+                }
+            }
+            Next::Find => {
+                state.status = Status::Info(format!("Looking for Home Assistant for {:?}...", DISCOVERY_TIMEOUT));
+                terminal.draw(|f| draw_setup(f, &state))?;
+                find_instances(rt, &mut state);
+            }
+        }
+    }
+}
+
+/// Edits the field that has focus & moves between them. Anything that needs the network is handed
+/// back for `setup_loop` to do.
+fn on_key(state: &mut SetupState, code: KeyCode, modifiers: KeyModifiers) -> Next {
+    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+    let field = match state.focus {
+        Field::Url => &mut state.url,
+        Field::Token => &mut state.token,
+    };
+    match code {
+        KeyCode::Esc => return Next::Quit,
+        KeyCode::Char('c') if ctrl => return Next::Quit,
+        KeyCode::Char('u') if ctrl => field.clear(),
+        KeyCode::Char('t') if ctrl => return Next::Test,
+        KeyCode::Char('s') if ctrl => return Next::Save,
+        KeyCode::Char('f') if ctrl => return Next::Find,
+        KeyCode::Char('n') if ctrl && !state.found.0.is_empty() => {
+            let next = state.found.1.selected().map(|idx| idx + 1).unwrap_or(0) % state.found.0.len();
+            pick(state, next);
+        }
+        KeyCode::Char(c) if !ctrl => field.push(c),
+        KeyCode::Backspace => {
+            field.pop();
+        }
+        KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+            state.focus = match state.focus {
+                Field::Url => Field::Token,
+                Field::Token => Field::Url,
+            };
+        }
+        KeyCode::Enter if state.focus == Field::Url => state.focus = Field::Token,
+        // Enter on the token tests the connection first & only saves if it worked.
+        KeyCode::Enter => return Next::TestAndSave,
+        _ => (),
+    }
+    Next::Nothing
+}
+
+fn find_instances(rt: &tokio::runtime::Runtime, state: &mut SetupState) {
+    match rt.block_on(discover(DISCOVERY_TIMEOUT)) {
+        Ok(found) if found.is_empty() => {
+            state.status = Status::Bad(String::from("Nothing answered, type the url in instead"));
+        }
+        Ok(found) => {
+            state.found.0 = found;
+            pick(state, 0);
+        }
+        Err(e) => state.status = Status::Bad(e.to_string()),
+    }
+}
+
+/// Puts the `idx`th instance found into the url box.
+fn pick(state: &mut SetupState, idx: usize) {
+    let instance = &state.found.0[idx];
+    state.url = instance.base_url.clone();
+    state.status = Status::Info(format!(
+        "Using {}, Ctrl+n for the next one. Now for the token.",
+        instance.name
+    ));
+    state.found.1.select(Some(idx));
+    state.focus = Field::Token;
+}
+
+/// The test blocks, so the status is drawn first to show something's going on.
+fn show_testing<B: Backend>(terminal: &mut Terminal<B>, state: &mut SetupState) -> io::Result<()> {
+    state.status = Status::Info(format!("Testing {}...", state.url.trim()));
+    terminal.draw(|f| draw_setup(f, state))?;
+    Ok(())
+}
+
+fn test_connection(rt: &tokio::runtime::Runtime, url: &str, token: &str) -> Status {
+    let url = url.trim().trim_end_matches('/');
+    if let Err(e) = check_url(url) {
+        return Status::Bad(format!("The url {}", e));
+    }
+    if token.trim().is_empty() {
+        return Status::Bad(String::from("The token can't be empty"));
+    }
+    let haos_conn = HomeAssistantConnection::new(url.to_string(), String::from("haoscli"));
+    haos_conn
+        .write()
+        .expect("Couldn't get the write lock on the token")
+        .set_long_live_token(token.trim().to_string());
+    let conn = haos_conn.read().expect("Couldn't get the read lock");
+    match rt.block_on(conn.get_api_status()) {
+        Ok(message) => Status::Good(format!("Connected: {}", message)),
+        Err(e) => Status::Bad(format!("Couldn't connect: {}", e)),
+    }
+}
+
+/// Writes the config, putting why it couldn't in the status otherwise.
+fn save(state: &mut SetupState, config_path: &str) -> bool {
+    let url = state.url.trim().trim_end_matches('/');
+    if let Err(e) = check_url(url) {
+        state.status = Status::Bad(format!("The url {}", e));
+        return false;
+    }
+    if state.token.trim().is_empty() {
+        state.status = Status::Bad(String::from("The token can't be empty"));
+        return false;
+    }
+    match write_config(Path::new(config_path), url, state.token.trim()) {
+        Ok(()) => true,
+        Err(e) => {
+            state.status = Status::Bad(format!("Couldn't write {}: {}", config_path, e));
+            false
+        }
+    }
+}
+
+fn draw_setup<B: Backend>(f: &mut Frame<B>, state: &SetupState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints(
+            [
+                Constraint::Length(4),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(3),
+                Constraint::Length(2),
+            ]
+            .as_ref(),
+        )
+        .split(f.size());
+
+    let welcome = Paragraph::new(vec![
+        Spans::from(Span::styled("Welcome to haoscli!", Style::default().fg(Color::Yellow))),
+        Spans::from("Point it at your Home Assistant & give it a long-lived access token, you can make one"),
+        Spans::from("under your profile in Home Assistant (Security tab)."),
+    ])
+    .wrap(Wrap { trim: true });
+    f.render_widget(welcome, chunks[0]);
+
+    let masked: String = "*".repeat(state.token.chars().count());
+    let inputs = [(Field::Url, "Url", state.url.as_str(), chunks[1]), (Field::Token, "Token", masked.as_str(), chunks[2])];
+    for (field, title, text, area) in inputs {
+        let style = match state.focus == field {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+        let input = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title).border_style(style));
+        f.render_widget(input, area);
+        if state.focus == field {
+            // Long tokens scroll off the end of the box, the cursor just sits at the edge then.
+            let x = area.x + 1 + (text.chars().count() as u16).min(area.width.saturating_sub(3));
+            f.set_cursor(x, area.y + 1);
+        }
+    }
+
+    let (text, color) = match &state.status {
+        Status::Info(text) => (text, Color::Reset),
+        Status::Good(text) => (text, Color::Green),
+        Status::Bad(text) => (text, Color::Red),
+    };
+    let status = Paragraph::new(Span::styled(text.as_str(), Style::default().fg(color)))
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+    let status_loc = match state.found.0.is_empty() {
+        true => chunks[3],
+        false => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(4), Constraint::Min(3)].as_ref())
+                .split(chunks[3]);
+            let items: Vec<ListItem> = state
+                .found
+                .0
+                .iter()
+                .map(|instance| {
+                    ListItem::new(format!(
+                        "{}  {}  {}",
+                        instance.name,
+                        instance.version.as_deref().unwrap_or("?"),
+                        instance.base_url
+                    ))
+                })
+                .collect();
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title("Found on the network"))
+                .highlight_symbol("> ");
+            // The list state only changes in `pick`, drawing doesn't need to hold on to it.
+            f.render_stateful_widget(list, split[1], &mut state.found.1.clone());
+            split[0]
+        }
+    };
+    f.render_widget(status, status_loc);
+
+    let help = Paragraph::new(
+        "Tab: next field  Enter: test & save  Ctrl+t: test  Ctrl+s: save without testing  Ctrl+f: find on the network  Ctrl+u: clear  Esc: quit",
+    )
+    .wrap(Wrap { trim: true });
+    f.render_widget(help, chunks[4]);
+}
+
+#[cfg(test)]
+mod tests;
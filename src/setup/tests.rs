@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use tui::{backend::TestBackend, Terminal};
+
+use haoscli::discovery::DiscoveredInstance;
+
+use super::{draw_setup, on_key, save, Field, Next, SetupState, Status};
+
+/// A fresh directory for a test to write into.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("haoscli-setup-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn press(state: &mut SetupState, code: KeyCode) -> Next {
+    on_key(state, code, KeyModifiers::NONE)
+}
+
+fn ctrl(state: &mut SetupState, c: char) -> Next {
+    on_key(state, KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+fn type_in(state: &mut SetupState, text: &str) {
+    for c in text.chars() {
+        assert_eq!(press(state, KeyCode::Char(c)), Next::Nothing);
+    }
+}
+
+/// The setup screen at 80x24, with the trailing spaces trimmed off each line.
+fn screen(state: &SetupState) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|f| draw_setup(f, state)).unwrap();
+    let buffer = terminal.backend().buffer();
+    (0..24)
+        .map(|y| (0..80).map(|x| buffer.get(x, y).symbol.as_str()).collect::<String>().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn typing_into_the_fields() {
+    let mut state = SetupState::new("config.toml");
+    ctrl(&mut state, 'u');
+    type_in(&mut state, "http://ha.lan:8123/");
+    press(&mut state, KeyCode::Backspace);
+    assert_eq!(state.url, "http://ha.lan:8123");
+
+    // Enter on the url moves on to the token, Tab & the arrows go back & forth.
+    assert_eq!(press(&mut state, KeyCode::Enter), Next::Nothing);
+    assert_eq!(state.focus, Field::Token);
+    type_in(&mut state, "secret");
+    press(&mut state, KeyCode::Up);
+    assert_eq!(state.focus, Field::Url);
+    press(&mut state, KeyCode::Tab);
+    ctrl(&mut state, 'u');
+    type_in(&mut state, "abc");
+    assert_eq!((state.url.as_str(), state.token.as_str()), ("http://ha.lan:8123", "abc"));
+
+    // Control keys that don't do anything aren't typed in.
+    assert_eq!(ctrl(&mut state, 'x'), Next::Nothing);
+    assert_eq!(ctrl(&mut state, 'n'), Next::Nothing);
+    assert_eq!(state.token, "abc");
+}
+
+#[test]
+fn keys_that_need_the_network() {
+    let mut state = SetupState::new("config.toml");
+    assert_eq!(ctrl(&mut state, 't'), Next::Test);
+    assert_eq!(ctrl(&mut state, 's'), Next::Save);
+    assert_eq!(ctrl(&mut state, 'f'), Next::Find);
+    assert_eq!(press(&mut state, KeyCode::Esc), Next::Quit);
+    assert_eq!(ctrl(&mut state, 'c'), Next::Quit);
+    press(&mut state, KeyCode::Tab);
+    assert_eq!(press(&mut state, KeyCode::Enter), Next::TestAndSave);
+}
+
+#[test]
+fn ctrl_n_goes_through_what_was_found() {
+    let instance = |name: &str, base_url: &str| DiscoveredInstance {
+        name: name.to_string(),
+        version: None,
+        base_url: base_url.to_string(),
+        uuid: None,
+    };
+    let mut state = SetupState::new("config.toml");
+    state.found.0 = vec![instance("Home", "http://192.168.1.20:8123"), instance("Cabin", "http://192.168.1.30:8123")];
+    super::pick(&mut state, 0);
+    assert_eq!((state.url.as_str(), state.focus), ("http://192.168.1.20:8123", Field::Token));
+
+    ctrl(&mut state, 'n');
+    assert_eq!(state.url, "http://192.168.1.30:8123");
+    ctrl(&mut state, 'n');
+    assert_eq!(state.url, "http://192.168.1.20:8123");
+    assert!(screen(&state).contains("> Home  ?  http://192.168.1.20:8123"));
+}
+
+#[test]
+fn save_checks_the_fields_first() {
+    let path = scratch_dir("save").join("haoscli").join("config.toml");
+    let config_path = path.to_str().unwrap();
+    let mut state = SetupState::new(config_path);
+
+    state.url = String::from("homeassistant.local");
+    state.token = String::from("abc");
+    assert!(!save(&mut state, config_path));
+    assert!(matches!(&state.status, Status::Bad(why) if why.starts_with("The url")));
+    assert!(screen(&state).contains("The url"));
+
+    state.url = String::from("http://ha.lan:8123/");
+    state.token = String::from("   ");
+    assert!(!save(&mut state, config_path));
+    assert!(matches!(&state.status, Status::Bad(why) if why == "The token can't be empty"));
+    assert!(!path.exists());
+
+    state.token = String::from(" abc ");
+    assert!(save(&mut state, config_path));
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.starts_with("url = \"http://ha.lan:8123\"\ntoken = \"abc\"\n"));
+}
+
+#[test]
+fn the_token_is_masked() {
+    let mut state = SetupState::new("config.toml");
+    press(&mut state, KeyCode::Tab);
+    type_in(&mut state, "hunter2");
+    let screen = screen(&state);
+    assert!(screen.contains("*******"));
+    assert!(!screen.contains("hunter2"));
+    assert!(screen.contains("http://homeassistant.local:8123"));
+    assert!(screen.contains("No config at config.toml yet"));
+}
@@ -0,0 +1,290 @@
+//! `haoscli snapshot`: saves the states to a file so they can be compared after an upgrade (IE:
+//! what went `unavailable` or changed units) and, for the things that can be set, put back.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use haoscli::types::{HomeAssistantConnection, State};
+
+use crate::cli::EntityFilter;
+use crate::output::{self, OutputFormat, Table};
+
+/// The domains `scene.apply` knows how to put back. Sensors & co. only report, there's nothing
+/// to set on them.
+const RESTORABLE: &[&str] = &[
+    "climate",
+    "cover",
+    "fan",
+    "humidifier",
+    "input_boolean",
+    "input_number",
+    "input_select",
+    "input_text",
+    "light",
+    "lock",
+    "media_player",
+    "number",
+    "select",
+    "switch",
+    "vacuum",
+    "water_heater",
+];
+
+#[derive(Debug, Clone, Copy)]
+enum FileFormat {
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl FileFormat {
+    fn new(path: &Path, forced: Option<&str>) -> Result<Self, String> {
+        let name = forced
+            .map(String::from)
+            .or_else(|| path.extension().map(|ext| ext.to_string_lossy().to_lowercase()));
+        match name.as_deref() {
+            Some("json") => Ok(FileFormat::Json),
+            Some("yaml") | Some("yml") => Ok(FileFormat::Yaml),
+            Some("csv") => Ok(FileFormat::Csv),
+            _ => Err(format!(
+                "Can't tell what format {} is, give it a .json, .yaml or .csv extension",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// A state as a csv row. Attributes don't fit in columns so they're kept as a json string.
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    entity_id: String,
+    state: String,
+    last_changed: DateTime<Utc>,
+    attributes: String,
+}
+
+/// One difference between two snapshots.
+#[derive(Serialize)]
+struct Change {
+    entity_id: String,
+    /// `added`, `removed` or `changed`.
+    change: &'static str,
+    /// `state` or `attributes.<name>`.
+    field: String,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+pub async fn run(
+    haos_conn: &HomeAssistantConnection,
+    matches: &ArgMatches,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("save", sub)) => {
+            let path = Path::new(sub.get_one::<String>("FILE").expect("FILE is a required argument"));
+            let file_format = FileFormat::new(path, sub.get_one::<String>("format").map(String::as_str))?;
+            let states = filtered(haos_conn.get_states().await?, sub);
+            write_states(path, file_format, &states)?;
+            eprintln!("Saved {} states to {}", states.len(), path.display());
+        }
+        Some(("diff", sub)) => {
+            let before = filtered(read_states(sub.get_one::<String>("BEFORE").expect("BEFORE is a required argument"))?, sub);
+            let after = match sub.get_one::<String>("AFTER") {
+                Some(path) => read_states(path)?,
+                None => haos_conn.get_states().await?,
+            };
+            let changes = diff(&before, &filtered(after, sub));
+            output::print(format, &changes, || Table {
+                header: vec!["ENTITY", "CHANGE", "FIELD", "BEFORE", "AFTER"],
+                rows: changes
+                    .iter()
+                    .map(|change| {
+                        vec![
+                            change.entity_id.clone(),
+                            change.change.to_string(),
+                            change.field.clone(),
+                            change.before.as_ref().map(as_text).unwrap_or_default(),
+                            change.after.as_ref().map(as_text).unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            })?;
+        }
+        Some(("restore", sub)) => {
+            let states = filtered(read_states(sub.get_one::<String>("FILE").expect("FILE is a required argument"))?, sub);
+            let (restorable, skipped): (Vec<State>, Vec<State>) = states.into_iter().partition(|state| {
+                let domain = state.entity_id.split('.').next().unwrap_or_default();
+                RESTORABLE.contains(&domain) && state.state != "unavailable" && state.state != "unknown"
+            });
+            if !skipped.is_empty() {
+                eprintln!("Skipping {} entities that can't be set (sensors, unavailable ones & the like)", skipped.len());
+            }
+
+            output::print(format, &restorable, || Table {
+                header: vec!["ENTITY", "STATE"],
+                rows: restorable
+                    .iter()
+                    .map(|state| vec![state.entity_id.clone(), state.state.clone()])
+                    .collect(),
+            })?;
+            if sub.contains_id("dry-run") {
+                eprintln!("Dry run, nothing was sent");
+                return Ok(());
+            }
+            if restorable.is_empty() {
+                return Ok(());
+            }
+
+            // scene.apply takes the state & attributes side by side, IE: `{"light.desk": {"state":
+            // "on", "brightness": 120}}`, and only sets the attributes each integration supports.
+            let mut entities = serde_json::Map::new();
+            for state in &restorable {
+                let mut target = match &state.attributes {
+                    Value::Object(attributes) => attributes.clone(),
+                    _ => serde_json::Map::new(),
+                };
+                target.insert(String::from("state"), json!(state.state));
+                entities.insert(state.entity_id.clone(), Value::Object(target));
+            }
+            haos_conn
+                .call_service("scene", "apply", &json!({ "entities": entities }))
+                .await?;
+            eprintln!("Restored {} entities", restorable.len());
+        }
+        _ => unreachable!("clap only lets through the subcommands we defined"),
+    }
+    Ok(())
+}
+
+fn filtered(mut states: Vec<State>, matches: &ArgMatches) -> Vec<State> {
+    let filter = EntityFilter::from_matches(matches);
+    states.retain(|state| filter.matches(Some(&state.entity_id)));
+    states
+}
+
+fn write_states(path: &Path, format: FileFormat, states: &[State]) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        FileFormat::Json => serde_json::to_writer_pretty(&mut out, states)?,
+        FileFormat::Yaml => serde_yaml::to_writer(&mut out, states)?,
+        FileFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            for state in states {
+                writer.serialize(CsvRow {
+                    entity_id: state.entity_id.clone(),
+                    state: state.state.clone(),
+                    last_changed: state.last_changed,
+                    attributes: state.attributes.to_string(),
+                })?;
+            }
+            writer.flush()?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn read_states(path: &str) -> Result<Vec<State>, Box<dyn Error>> {
+    let path = Path::new(path);
+    let format = FileFormat::new(path, None)?;
+    let file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+    let states = match format {
+        FileFormat::Json => serde_json::from_reader(BufReader::new(file))?,
+        FileFormat::Yaml => serde_yaml::from_reader(BufReader::new(file))?,
+        FileFormat::Csv => {
+            let mut states = Vec::new();
+            for row in csv::Reader::from_reader(file).deserialize() {
+                let row: CsvRow = row?;
+                states.push(State {
+                    entity_id: row.entity_id,
+                    state: row.state,
+                    last_changed: row.last_changed,
+                    attributes: serde_json::from_str(&row.attributes)?,
+                });
+            }
+            states
+        }
+    };
+    Ok(states)
+}
+
+/// Everything that was added, removed or changed, sorted by entity. `last_changed` is left out,
+/// it'd show up for nearly everything after a restart.
+fn diff(before: &[State], after: &[State]) -> Vec<Change> {
+    let before: BTreeMap<&str, &State> = before.iter().map(|state| (state.entity_id.as_str(), state)).collect();
+    let after: BTreeMap<&str, &State> = after.iter().map(|state| (state.entity_id.as_str(), state)).collect();
+
+    let mut changes = Vec::new();
+    for (entity_id, old) in &before {
+        let Some(new) = after.get(entity_id) else {
+            changes.push(Change {
+                entity_id: entity_id.to_string(),
+                change: "removed",
+                field: String::from("state"),
+                before: Some(json!(old.state)),
+                after: None,
+            });
+            continue;
+        };
+
+        if old.state != new.state {
+            changes.push(Change {
+                entity_id: entity_id.to_string(),
+                change: "changed",
+                field: String::from("state"),
+                before: Some(json!(old.state)),
+                after: Some(json!(new.state)),
+            });
+        }
+
+        let empty = serde_json::Map::new();
+        let old_attributes = old.attributes.as_object().unwrap_or(&empty);
+        let new_attributes = new.attributes.as_object().unwrap_or(&empty);
+        let mut keys: Vec<&String> = old_attributes.keys().chain(new_attributes.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let (old_value, new_value) = (old_attributes.get(key), new_attributes.get(key));
+            if old_value != new_value {
+                changes.push(Change {
+                    entity_id: entity_id.to_string(),
+                    change: "changed",
+                    field: format!("attributes.{}", key),
+                    before: old_value.cloned(),
+                    after: new_value.cloned(),
+                });
+            }
+        }
+    }
+    for (entity_id, new) in &after {
+        if !before.contains_key(entity_id) {
+            changes.push(Change {
+                entity_id: entity_id.to_string(),
+                change: "added",
+                field: String::from("state"),
+                before: None,
+                after: Some(json!(new.state)),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.entity_id.cmp(&b.entity_id));
+    changes
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(v) => v.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
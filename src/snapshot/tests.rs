@@ -0,0 +1,66 @@
+use serde_json::{json, Value};
+
+use haoscli::types::State;
+
+use super::diff;
+
+fn states(states: Value) -> Vec<State> {
+    serde_json::from_value(states).unwrap()
+}
+
+/// As `diff` would be printed with `--output json`.
+fn changes(before: &[State], after: &[State]) -> Value {
+    serde_json::to_value(diff(before, after)).unwrap()
+}
+
+#[test]
+fn added_removed_and_changed() {
+    let before = states(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+        {"entity_id": "sensor.old", "state": "12", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+    ]));
+    let after = states(json!([
+        {"entity_id": "sensor.new", "state": "3", "last_changed": "2024-01-02T10:00:00+00:00", "attributes": {}},
+        {"entity_id": "light.desk", "state": "unavailable", "last_changed": "2024-01-02T10:00:00+00:00", "attributes": {}},
+    ]));
+    assert_eq!(
+        changes(&before, &after),
+        json!([
+            {"entity_id": "light.desk", "change": "changed", "field": "state", "before": "on", "after": "unavailable"},
+            {"entity_id": "sensor.new", "change": "added", "field": "state", "before": null, "after": "3"},
+            {"entity_id": "sensor.old", "change": "removed", "field": "state", "before": "12", "after": null},
+        ])
+    );
+}
+
+#[test]
+fn attribute_only_differences() {
+    let before = states(json!([
+        {"entity_id": "sensor.power", "state": "120", "last_changed": "2024-01-01T10:00:00+00:00",
+            "attributes": {"unit_of_measurement": "W", "icon": "mdi:flash", "friendly_name": "Power"}},
+    ]));
+    let after = states(json!([
+        {"entity_id": "sensor.power", "state": "120", "last_changed": "2024-01-01T10:00:00+00:00",
+            "attributes": {"unit_of_measurement": "kW", "friendly_name": "Power", "device_class": "power"}},
+    ]));
+    assert_eq!(
+        changes(&before, &after),
+        json!([
+            {"entity_id": "sensor.power", "change": "changed", "field": "attributes.device_class", "before": null, "after": "power"},
+            {"entity_id": "sensor.power", "change": "changed", "field": "attributes.icon", "before": "mdi:flash", "after": null},
+            {"entity_id": "sensor.power", "change": "changed", "field": "attributes.unit_of_measurement", "before": "W", "after": "kW"},
+        ])
+    );
+}
+
+#[test]
+fn last_changed_alone_isnt_a_change() {
+    let before = states(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {"brightness": 120}},
+    ]));
+    let after = states(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-02T08:30:00+00:00", "attributes": {"brightness": 120}},
+    ]));
+    assert_eq!(changes(&before, &after), json!([]));
+    assert_eq!(changes(&[], &[]), json!([]));
+}
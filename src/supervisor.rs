@@ -0,0 +1,154 @@
+//! Calls against the Supervisor (hassio) API. On HA OS installs this is reachable through the core
+//! proxy at `/api/hassio`, so the same long lived token works.
+use std::path::Path;
+
+use log::{debug, info};
+
+use tokio::io::AsyncWriteExt;
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::check_status;
+use crate::error::{Error, Result};
+use crate::types::{
+    Addon, AddonAction, AddonInfo, Backup, HomeAssistantConnection, NewBackup, SupervisorResponse,
+};
+
+impl HomeAssistantConnection {
+    pub async fn get_addons(&self) -> Result<Vec<Addon>> {
+        #[derive(Deserialize)]
+        struct Addons {
+            addons: Vec<Addon>,
+        }
+
+        let req = self.build_base_get_request("/hassio/addons");
+        let addons: Addons = supervisor_data(self.send(req).await?).await?;
+        Ok(addons.addons)
+    }
+
+    pub async fn get_addon_info(&self, slug: &str) -> Result<AddonInfo> {
+        let req = self.build_base_get_request(format!("/hassio/addons/{}/info", slug).as_str());
+        supervisor_data(self.send(req).await?).await
+    }
+
+    pub async fn addon_action(&self, slug: &str, action: AddonAction) -> Result<()> {
+        let verb = match action {
+            AddonAction::Start => "start",
+            AddonAction::Stop => "stop",
+            AddonAction::Restart => "restart",
+            AddonAction::Update => "update",
+        };
+        info!("Asking the supervisor to {} {}", verb, slug);
+        let req = self.build_base_post_request(format!("/hassio/addons/{}/{}", slug, verb).as_str());
+        supervisor_ok(self.send_unbounded(req).await?).await
+    }
+
+    pub async fn start_addon(&self, slug: &str) -> Result<()> {
+        self.addon_action(slug, AddonAction::Start).await
+    }
+
+    pub async fn stop_addon(&self, slug: &str) -> Result<()> {
+        self.addon_action(slug, AddonAction::Stop).await
+    }
+
+    pub async fn restart_addon(&self, slug: &str) -> Result<()> {
+        self.addon_action(slug, AddonAction::Restart).await
+    }
+
+    pub async fn update_addon(&self, slug: &str) -> Result<()> {
+        self.addon_action(slug, AddonAction::Update).await
+    }
+
+    /// The logs come back as plain text rather than the usual json envelope.
+    pub async fn get_addon_logs(&self, slug: &str) -> Result<String> {
+        let req = self
+            .build_base_get_request(format!("/hassio/addons/{}/logs", slug).as_str())
+            .header("accept", "text/plain");
+        let resp = check_status(self.send(req).await?).await?;
+        Ok(resp.text().await?)
+    }
+
+    pub async fn get_backups(&self) -> Result<Vec<Backup>> {
+        #[derive(Deserialize)]
+        struct Backups {
+            backups: Vec<Backup>,
+        }
+
+        let req = self.build_base_get_request("/hassio/backups");
+        let backups: Backups = supervisor_data(self.send(req).await?).await?;
+        Ok(backups.backups)
+    }
+
+    /// Creates the backup and hands back its slug. This only returns once the Supervisor is done,
+    /// which can take minutes for a full backup.
+    pub async fn create_backup(&self, backup: &NewBackup) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Created {
+            slug: String,
+        }
+
+        let end_point = match backup.partial {
+            true => "/hassio/backups/new/partial",
+            false => "/hassio/backups/new/full",
+        };
+        info!("Creating a backup through {}", end_point);
+        let req = self.build_base_post_request(end_point).json(backup);
+        let created: Created = supervisor_data(self.send_unbounded(req).await?).await?;
+        Ok(created.slug)
+    }
+
+    pub async fn delete_backup(&self, slug: &str) -> Result<()> {
+        info!("Deleting backup {}", slug);
+        let req = self.build_base_delete_request(format!("/hassio/backups/{}", slug).as_str());
+        supervisor_ok(self.send(req).await?).await
+    }
+
+    /// Streams the backup tarball into `path` and returns how many bytes were written.
+    pub async fn download_backup(&self, slug: &str, path: &Path) -> Result<u64> {
+        let req = self.build_base_get_request(format!("/hassio/backups/{}/download", slug).as_str());
+        let mut resp = check_status(self.send_unbounded(req).await?).await?;
+
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut written: u64 = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        file.flush().await?;
+        info!("Downloaded backup {} to {} ({} bytes)", slug, path.display(), written);
+        Ok(written)
+    }
+}
+
+/// Unwraps the `data` out of the Supervisor's envelope.
+async fn supervisor_data<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+    let resp: SupervisorResponse<T> = supervisor_status(resp).await?.json().await?;
+    match (resp.result.as_str(), resp.data) {
+        ("ok", Some(data)) => Ok(data),
+        _ => Err(Error::Supervisor(resp.message.unwrap_or(resp.result))),
+    }
+}
+
+/// Same as `supervisor_data` but for the calls that don't hand any data back.
+async fn supervisor_ok(resp: reqwest::Response) -> Result<()> {
+    let resp: SupervisorResponse<serde_json::Value> = supervisor_status(resp).await?.json().await?;
+    debug!("supervisor responded with: {:?}", resp);
+    match resp.result.as_str() {
+        "ok" => Ok(()),
+        _ => Err(Error::Supervisor(resp.message.unwrap_or(resp.result))),
+    }
+}
+
+/// The Supervisor explains its failures in the envelope even when the status code is an error, so
+/// that message is pulled out rather than handing back the raw body.
+async fn supervisor_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    match check_status(resp).await {
+        Err(Error::Status(code, body)) => {
+            match serde_json::from_str::<SupervisorResponse<serde_json::Value>>(&body) {
+                Ok(SupervisorResponse { message: Some(msg), .. }) => Err(Error::Supervisor(msg)),
+                _ => Err(Error::Status(code, body)),
+            }
+        }
+        other => other,
+    }
+}
@@ -0,0 +1,88 @@
+//! Builds the HTTP client and the websocket's TLS connector out of a `TlsConfig`, so custom CAs,
+//! client certificates and the proxy apply to every request we make.
+use std::fs;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::types::TlsConfig;
+
+/// A host that's rebooting doesn't refuse connections, it just doesn't answer. Without this we'd sit
+/// there for however long the OS gives up after, which is minutes.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl TlsConfig {
+    /// Makes sure `accept_invalid_certs_for` names the host we're talking to, it's only ever meant
+    /// as an opt-in for that one host.
+    fn accept_invalid_certs(&self, host: &str) -> Result<bool> {
+        match &self.accept_invalid_certs_for {
+            None => Ok(false),
+            Some(allowed) if allowed.eq_ignore_ascii_case(host) => {
+                warn!("Certificate validation is turned off for {}", host);
+                Ok(true)
+            }
+            Some(allowed) => Err(Error::Config(format!(
+                "accept_invalid_certs_for is set to {} but the url points at {}",
+                allowed, host
+            ))),
+        }
+    }
+
+    fn read_client_identity(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Ok(Some((fs::read(cert)?, fs::read(key)?))),
+            (None, None) => Ok(None),
+            _ => Err(Error::Config(String::from(
+                "client_cert and client_key have to be given together",
+            ))),
+        }
+    }
+
+    pub(crate) fn build_client(&self, host: &str) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .danger_accept_invalid_certs(self.accept_invalid_certs(host)?);
+        if let Some(ca_bundle) = &self.ca_bundle {
+            info!("Trusting the CA certificates in {}", ca_bundle.display());
+            for cert in split_pem_certificates(&fs::read(ca_bundle)?) {
+                builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&cert)?);
+            }
+        }
+        if let Some((cert, key)) = self.read_client_identity()? {
+            builder = builder.identity(reqwest::Identity::from_pkcs8_pem(&cert, &key)?);
+        }
+        if let Some(proxy) = &self.proxy {
+            info!("Sending every request through the proxy at {}", proxy);
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+        Ok(builder.build()?)
+    }
+
+    #[cfg(feature = "websocket")]
+    pub(crate) fn build_connector(&self, host: &str) -> Result<native_tls::TlsConnector> {
+        let tls_err = |e: native_tls::Error| Error::Config(e.to_string());
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.danger_accept_invalid_certs(self.accept_invalid_certs(host)?);
+        if let Some(ca_bundle) = &self.ca_bundle {
+            for cert in split_pem_certificates(&fs::read(ca_bundle)?) {
+                builder.add_root_certificate(native_tls::Certificate::from_pem(&cert).map_err(tls_err)?);
+            }
+        }
+        if let Some((cert, key)) = self.read_client_identity()? {
+            builder.identity(native_tls::Identity::from_pkcs8(&cert, &key).map_err(tls_err)?);
+        }
+        builder.build().map_err(tls_err)
+    }
+}
+
+/// CA bundles usually hold more than one certificate but `from_pem` only takes the first, so this
+/// splits the bundle up on the `BEGIN CERTIFICATE` markers.
+fn split_pem_certificates(bundle: &[u8]) -> Vec<Vec<u8>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let text = String::from_utf8_lossy(bundle);
+    text.split_inclusive(END)
+        .filter(|chunk| chunk.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|chunk| chunk.trim_start().as_bytes().to_vec())
+        .collect()
+}
@@ -1,19 +1,20 @@
 use std::{
     collections::HashMap,
-    sync::{RwLock, Weak},
+    path::PathBuf,
+    sync::{Arc, RwLock, Weak},
+    time::Duration,
 };
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 
 use serde::{Deserialize, Serialize};
 
-trait HomeAssistantStateful {
-    fn get(&self);
-}
+use crate::cassette::Cassette;
 
 /// Struct related to the HomeAssistant instance
-/// Currently only handles long term token and uses the REST end points.
-#[derive(Debug)]
+/// Currently only handles long term token and uses the REST end points. Cloning it is cheap, the
+/// client & cassette are shared, so a task can take its own copy rather than hold the lock.
+#[derive(Debug, Clone)]
 pub struct HomeAssistantConnection {
     /// The URL which you are connecting to
     pub url: String,
@@ -25,10 +26,35 @@ pub struct HomeAssistantConnection {
     pub retries: i32,
     /// A weak reference to ourself so that we can lock
     pub lock: Weak<RwLock<Self>>,
+    /// Shared by every REST request, rebuilt whenever the TLS config changes.
+    pub client: reqwest::Client,
+    /// The TLS and proxy settings the client and websocket are built with.
+    pub tls: TlsConfig,
+    /// Set when the requests are being recorded or replayed, see `cassette`.
+    pub cassette: Option<Arc<Cassette>>,
+    /// How long a request gets to be answered, 30s to start with. The Supervisor's slow calls
+    /// (add-on actions & backups) aren't held to it.
+    pub request_timeout: Duration,
+}
+
+/// How to set up TLS and proxying for the requests we make. The defaults are the same as a plain
+/// `reqwest::Client`.
+#[derive(Debug, Default, Clone)]
+pub struct TlsConfig {
+    /// PEM file with extra CA certificates to trust, IE: the CA your LAN certificates came from.
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip certificate validation, only for this host. It has to match the host in the url.
+    pub accept_invalid_certs_for: Option<String>,
+    /// PEM client certificate for mutual TLS, needs `client_key` too.
+    pub client_cert: Option<PathBuf>,
+    /// PKCS#8 PEM private key that goes with `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// HTTP(S) proxy every request goes through, IE: http://proxy.lan:3128
+    pub proxy: Option<String>,
 }
 
 /// An enum for the token. This is created for holding purposes.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 pub enum Token {
     LongLivedToken(String),
@@ -36,7 +62,7 @@ pub enum Token {
 }
 
 /// Struct to hold data about an event listing
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Event {
     pub event: String,
     pub listener_count: i32,
@@ -58,11 +84,6 @@ pub struct RequestServiceStruct <'a> {
     pub service: &'a str,
 }
 
-impl HomeAssistantStateful for Service {
-    fn get(&self) {
-    }
-}
-
 
 /// Used to create a request information about an entity, passes in the entity id.
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -71,7 +92,7 @@ pub struct RequestEntityObject<'a> {
 }
 
 /// Holds the state informaiton about the Entities in the HAOS instance.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct State {
     pub entity_id: String,
     pub state: String,
@@ -85,3 +106,203 @@ pub struct State {
 pub struct RequestStateStruct {
     pub state: HashMap<String, String>,
 }
+
+/// The envelope the Supervisor wraps every answer in, IE: `{"result": "ok", "data": {...}}`.
+#[derive(Debug, Deserialize)]
+pub struct SupervisorResponse<T> {
+    pub result: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub data: Option<T>,
+}
+
+/// An add-on as listed by the Supervisor. Only the bits we show are kept.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Addon {
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub description: String,
+    /// The installed version, `None` if the add-on isn't installed.
+    pub version: Option<String>,
+    pub version_latest: Option<String>,
+    #[serde(default)]
+    pub update_available: bool,
+    /// IE: started, stopped, unknown. `None` if the add-on isn't installed.
+    pub state: Option<String>,
+}
+
+/// The detailed information about a single add-on.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AddonInfo {
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub description: String,
+    pub version: Option<String>,
+    pub version_latest: Option<String>,
+    #[serde(default)]
+    pub update_available: bool,
+    pub state: Option<String>,
+    /// Either auto or manual.
+    #[serde(default)]
+    pub boot: String,
+    #[serde(default)]
+    pub auto_update: bool,
+    pub hostname: Option<String>,
+    pub url: Option<String>,
+    /// The add-on's own configuration, this differs per add-on so it's kept as raw json.
+    #[serde(default)]
+    pub options: serde_json::Value,
+}
+
+/// The things you can ask the Supervisor to do to an add-on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddonAction {
+    Start,
+    Stop,
+    Restart,
+    Update,
+}
+
+/// A backup as listed by the Supervisor.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Backup {
+    pub slug: String,
+    pub name: String,
+    pub date: DateTime<Utc>,
+    /// Either full or partial.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Size in MB.
+    #[serde(default)]
+    pub size: f64,
+    /// Whether the backup is password protected.
+    #[serde(default)]
+    pub protected: bool,
+}
+
+/// What to put in a new backup. Leaving `partial` off makes a full backup and ignores the add-on
+/// and folder lists.
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct NewBackup {
+    #[serde(skip)]
+    pub partial: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Only used for partial backups, whether to include the Home Assistant config.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homeassistant: Option<bool>,
+    /// Only used for partial backups, the add-on slugs to include.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addons: Vec<String>,
+    /// Only used for partial backups, IE: share, ssl, media.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub folders: Vec<String>,
+}
+
+/// How the recorder's long-term statistics get bucketed.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatisticsPeriod {
+    #[serde(rename = "5minute")]
+    FiveMinute,
+    #[default]
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+/// One bucket of a long-term statistic. Which of the values are filled in depends on the
+/// statistic, energy meters have `sum`/`change` while things like temperatures have `mean`.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct StatisticValue {
+    #[serde(deserialize_with = "de_timestamp")]
+    pub start: DateTime<Utc>,
+    #[serde(default, deserialize_with = "de_opt_timestamp")]
+    pub end: Option<DateTime<Utc>>,
+    pub change: Option<f64>,
+    pub sum: Option<f64>,
+    pub state: Option<f64>,
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// The energy dashboard configuration, IE: which statistics make up the grid, solar, etc.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct EnergyPreferences {
+    #[serde(default)]
+    pub energy_sources: Vec<EnergySource>,
+    #[serde(default)]
+    pub device_consumption: Vec<DeviceConsumption>,
+}
+
+/// A source of energy on the dashboard. Grids list their meters in `flow_from`/`flow_to`, the
+/// rest (solar, battery, gas) use the `stat_energy_*` fields directly.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct EnergySource {
+    /// IE: grid, solar, battery, gas
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub flow_from: Vec<EnergyFlow>,
+    #[serde(default)]
+    pub flow_to: Vec<EnergyFlow>,
+    pub stat_energy_from: Option<String>,
+    pub stat_energy_to: Option<String>,
+}
+
+/// A single grid meter, importing has `stat_energy_from` and exporting `stat_energy_to`.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct EnergyFlow {
+    pub stat_energy_from: Option<String>,
+    pub stat_energy_to: Option<String>,
+}
+
+/// A device whose consumption is tracked on the energy dashboard.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct DeviceConsumption {
+    pub stat_consumption: String,
+}
+
+/// Newer versions of Home Assistant send the statistic timestamps as milliseconds since the epoch,
+/// older ones as ISO strings. This takes either.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Millis(f64),
+    Iso(DateTime<Utc>),
+}
+
+impl Timestamp {
+    fn into_utc<E: serde::de::Error>(self) -> Result<DateTime<Utc>, E> {
+        match self {
+            Timestamp::Iso(v) => Ok(v),
+            Timestamp::Millis(ms) => Utc
+                .timestamp_millis_opt(ms as i64)
+                .single()
+                .ok_or_else(|| E::custom(format!("{} is out of range", ms))),
+        }
+    }
+}
+
+fn de_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Timestamp::deserialize(deserializer)?.into_utc()
+}
+
+/// Same as `de_timestamp`, but a `null` is `None` rather than an error.
+fn de_opt_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Timestamp>::deserialize(deserializer)?
+        .map(Timestamp::into_utc)
+        .transpose()
+}
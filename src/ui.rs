@@ -1,16 +1,21 @@
 use std::{
     borrow::Cow,
+    collections::{HashMap, HashSet},
     io,
-    sync::{Arc, Condvar, Mutex},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Once,
+    },
+    time::{Duration, Instant},
 };
 
 use tui::{
-    backend::CrosstermBackend,
+    backend::{Backend, CrosstermBackend},
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{self, Block, Borders, Cell, List, ListItem, ListState, Row, Table, TableState, Paragraph, StatefulWidget, Widget},
-    Terminal,
+    widgets::{self, BarChart, Block, Borders, Cell, List, ListItem, ListState, Row, Table, Paragraph},
+    Frame, Terminal,
 };
 
 use crossterm::{
@@ -20,15 +25,30 @@ use crossterm::{
 };
 
 use haoscli::types::Event as HAEvent;
+use tokio::sync::mpsc::UnboundedSender;
 
-use haoscli::types::{Service, State};
+use haoscli::types::{Addon, Backup, StatisticsPeriod};
 
-use crate::ui_types::{ServicesPopUpElement, EventsPopUpElement, StatesPopUpElement, BuildPopup, BuildList, BuildTable, Pane, PopUpPane, UiState};
+use chrono::Local;
 
+use crate::actions::{Action, Command, Quit, Resource, Response};
+use crate::cache::CacheFile;
+use crate::logging;
+use crate::reducer::reduce;
+use crate::ui_types::{EnergySummary, LogPane, ServicesPopUpElement, StatesPopUpElement, BuildPopup, BuildTable, Offline, Pane, PopUpPane, Queued, Request, UiState};
 
-use log::{debug, info};
+
+use log::{debug, info, warn, Level};
 
 const POPUP_OFFSET: u16 = 5;
+/// How often a pending request's spinner moves on a frame.
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+/// How many requests are shown at once, the newest win.
+const REQUESTS_SHOWN: usize = 5;
+/// The most often the cache is rewritten while fresh lists keep coming in, so a crash or a power
+/// cut loses at most this much rather than everything since launch.
+const CACHE_SAVE_EVERY: Duration = Duration::from_secs(60);
 
 fn reset_terminal() -> Result<(), ()> {
     disable_raw_mode().expect("couldn't disable raw mode");
@@ -37,9 +57,33 @@ fn reset_terminal() -> Result<(), ()> {
     Ok(())
 }
 
+/// Puts the terminal back before a panic says what went wrong, otherwise the message is lost with
+/// the alternate screen & the shell is left in raw mode. Both the setup screen & the UI call this,
+/// only the first call sets the hook.
+pub fn restore_on_panic() {
+    static HOOKED: Once = Once::new();
+    HOOKED.call_once(|| {
+        let original_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |panic| {
+            reset_terminal().unwrap();
+            original_hook(panic);
+        }));
+    });
+}
+
 
-/// This function loops until quit is called. It draws each UI element.
-pub fn draw_ui(state: &mut Arc<Mutex<UiState>>, convar: &mut Arc<Condvar>) {
+/// This function loops until quit is called. It owns the UI state, hands every action to the
+/// reducer, passes the commands that come back to the fetcher and redraws once it's caught up. A
+/// tick goes to the reducer as often as the quickest of the `intervals` so it knows when to poll.
+/// The cache, if there is one, is shown until the first fetches come back & saved as fresh lists
+/// arrive. Gives back why it closed once the terminal's been put back.
+pub fn draw_ui(
+    actions: Receiver<Action>,
+    commands: UnboundedSender<Command>,
+    intervals: HashMap<Resource, Duration>,
+    offline_queue: Option<Duration>,
+    cache: Option<CacheFile>,
+) -> Quit {
     info!("Entered draw_ui for the first time");
     enable_raw_mode().expect("Could not enable raw mode");
     let mut std_out = std::io::stdout();
@@ -48,12 +92,120 @@ pub fn draw_ui(state: &mut Arc<Mutex<UiState>>, convar: &mut Arc<Condvar>) {
     let backend = CrosstermBackend::new(std_out);
     let mut terminal = Terminal::new(backend).expect("Could not load the backend");
 
-    let original_hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |panic| {
-        reset_terminal().unwrap();
-        original_hook(panic);
-    }));
+    restore_on_panic();
+
+    let mut state = UiState::default();
+    state.events.1.select(Some(0));
+    state.services.1.select(Some(0));
+    state.states.1.select(Some(0));
+    state.addons.1.select(Some(0));
+    state.backups.1.select(Some(0));
+    let poll_rate = intervals.values().min().copied().unwrap_or(Duration::from_secs(1));
+    state.intervals = intervals;
+    state.offline_queue = offline_queue;
+    if let Some(cached) = cache.as_ref().and_then(CacheFile::load) {
+        state.events.0 = cached.events;
+        state.services.0 = cached.services;
+        state.states.0 = cached.states;
+        state.stale = HashSet::from([Resource::Events, Resource::Services, Resource::States]);
+        state.cached_at = Some(cached.saved);
+    }
+
+
+    // The first tick goes out straight away so there's something to show.
+    let mut next_tick = Instant::now();
+    let mut unsaved = false;
+    let mut last_saved: Option<Instant> = None;
+    'ui_loop: loop {
+        let mut wait = next_tick.saturating_duration_since(Instant::now());
+        // Spinners need to turn faster than things get polled.
+        if state.requests.iter().any(Request::is_pending) {
+            wait = wait.min(SPINNER_FRAME);
+        }
+        let action = match actions.recv_timeout(wait) {
+            Ok(action) => action,
+            Err(RecvTimeoutError::Timeout) if Instant::now() < next_tick => {
+                paint(&mut terminal, &mut state);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                next_tick = Instant::now() + poll_rate;
+                Action::Tick
+            }
+            Err(RecvTimeoutError::Disconnected) => break 'ui_loop,
+        };
+        // Everything that's queued up is dealt with before painting, no point drawing in between.
+        for action in std::iter::once(action).chain(actions.try_iter()) {
+            unsaved |= matches!(
+                action,
+                Action::Response(Response::Events(Ok(_)) | Response::Services(Ok(_)) | Response::States(Ok(_)))
+            );
+            for command in reduce(&mut state, action) {
+                if commands.send(command).is_err() {
+                    warn!("The fetcher has stopped, the command was dropped");
+                }
+            }
+            if state.active == Pane::None {
+                info!("Quitting since we were told to");
+                break 'ui_loop;
+            }
+        }
+        let save_due = last_saved.is_none_or(|saved| saved.elapsed() >= CACHE_SAVE_EVERY);
+        if unsaved && save_due && save_cache(cache.as_ref(), &state) {
+            unsaved = false;
+            last_saved = Some(Instant::now());
+        }
+        debug!("Repainting the UI");
+        paint(&mut terminal, &mut state);
+    }
+
+    if unsaved {
+        save_cache(cache.as_ref(), &state);
+    }
+
+    disable_raw_mode().expect("couldn't disable raw mode");
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+    )
+    .expect("Couldn't close everything out");
+
+    // A signal doesn't ask first, so this is the only word of it outside the log.
+    if !state.queue.0.is_empty() {
+        warn!("Dropping {} queued changes that were never sent", state.queue.0.len());
+        eprintln!("Dropped {} queued changes that were never sent", state.queue.0.len());
+    }
+    state.quitting.unwrap_or(Quit::Key)
+}
+
+/// Writes the lists to the cache, but only once everything's been fetched, a stale list would get
+/// saved with a time it isn't from. Changes that haven't been confirmed yet are left out. True
+/// when it was tried, whether or not the write worked, so a failing one is only warned about every
+/// so often.
+fn save_cache(cache: Option<&CacheFile>, state: &UiState) -> bool {
+    let fetched_all = [Resource::Events, Resource::Services, Resource::States]
+        .iter()
+        .all(|resource| state.fetched.contains_key(resource));
+    let Some(cache) = cache.filter(|_| fetched_all && state.stale.is_empty()) else {
+        return false;
+    };
+    if let Err(e) = cache.save(state.events.0.clone(), state.services.0.clone(), state.confirmed_states()) {
+        warn!("Couldn't save the cache to {}: {}", cache.path.display(), e);
+    }
+    true
+}
+
+/// Draws the whole UI for `ui_state` onto any backend, the crossterm one in `draw_ui` or a
+/// `TestBackend` in the tests.
+pub fn paint<B: Backend>(terminal: &mut Terminal<B>, ui_state: &mut UiState) {
+    terminal
+        .draw(|f| render(f, ui_state))
+        .expect("Failed to draw the terminal UI");
+}
 
+/// One frame of the UI: whichever pane is active, the pop up over it & the banners on top.
+pub fn render<B: Backend>(f: &mut Frame<B>, ui_state: &mut UiState) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
@@ -66,90 +218,160 @@ pub fn draw_ui(state: &mut Arc<Mutex<UiState>>, convar: &mut Arc<Condvar>) {
             .as_ref(),
         );
 
-    let mut paint_ui = || {
-        let mut lock_state = state.lock().unwrap();
-        //debug!("{:#?}", lock_state);
-
-        let event_list_items: Vec<_> = lock_state
-            .events
-            .0
-            .iter()
-            .map(|event| {
-                ListItem::new(Spans::from(vec![Span::styled(
-                    event.event.clone(),
-                    Style::default(),
-                )]))
-            })
-            .collect();
-
-        let services_table_rows: Vec<_> = lock_state
-            .services
-            .0
-            .iter()
-            .map(|service| {
-                let mut cells: Vec<Cell> = vec![
-                    Cell::from(Cow::Owned(service.domain.to_string())).style(Style::default())
-                ];
-                cells.push(Cell::from(Cow::Owned(service.services.to_string())));
-                Row::new(cells)
-            })
-            .collect();
-
-        let state_list_items: Vec<_> = lock_state
-            .states
-            .0
-            .iter()
-            .map(|state| {
-                ListItem::new(Spans::from(vec![Span::styled(
-                    state.entity_id.clone(),
-                    Style::default(),
-                )]))
-            })
-            .collect();
-
-        //debug!("Service table rows: {:#?}", services_table_rows);
-
-        terminal.draw(|f| {
-            let size = f.size();
-            let locs = chunks.split(size);
-            let event_list_element = List::new(event_list_items)
-                .highlight_style(Style::default().bg(Color::Yellow))
-                .block(Block::default().title("Services").borders(Borders::ALL));
-            f.render_stateful_widget(event_list_element, locs[0], &mut lock_state.events.1);
-
-            let services_table_element = Table::new(services_table_rows)
-                .style(Style::default())
-                .highlight_style(Style::default().bg(Color::Yellow))
-                .header(Row::new(vec!["Service Name", "Service Details"]))
-                .block(Block::default())
-                .widths(&[
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(90),
-                ]);
-            f.render_stateful_widget(services_table_element, locs[1], &mut lock_state.services.1);
-
-            let states_list_element = List::new(state_list_items)
-                .block(Block::default().borders(Borders::ALL).title("States"))
-                .highlight_style(Style::default().bg(Color::Yellow))
-                .style(Style::default());
-            f.render_stateful_widget(states_list_element, locs[2], &mut lock_state.states.1);
-
-            let popup_block: Rect;
-            {
-                let x = f.size().left() + POPUP_OFFSET;
-                let y = f.size().top() + POPUP_OFFSET;
-
-                let width = f.size().right() - POPUP_OFFSET;
-                let height = f.size().bottom() - POPUP_OFFSET;
-
-                popup_block = Rect{x, y, width, height};
+    let event_list_items: Vec<_> = ui_state
+        .events
+        .0
+        .iter()
+        .map(|event| {
+            ListItem::new(Spans::from(vec![Span::styled(
+                event.event.clone(),
+                Style::default(),
+            )]))
+        })
+        .collect();
+
+    let services_table_rows: Vec<_> = ui_state
+        .services
+        .0
+        .iter()
+        .map(|service| {
+            let mut cells: Vec<Cell> = vec![
+                Cell::from(Cow::Owned(service.domain.to_string())).style(Style::default())
+            ];
+            cells.push(Cell::from(Cow::Owned(service.services.to_string())));
+            Row::new(cells)
+        })
+        .collect();
+
+    let state_list_items: Vec<_> = ui_state
+        .states
+        .0
+        .iter()
+        .map(|state| {
+            let mut spans = vec![Span::styled(state.entity_id.clone(), Style::default())];
+            // Showing what a change said it did, the next fetch has the final say.
+            if ui_state.unconfirmed.contains_key(&state.entity_id) {
+                spans.push(Span::styled(
+                    format!("  {} (pending confirmation)", state.state),
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+
+    let size = f.size();
+    let popup_block: Rect;
+    {
+        // A terminal smaller than the offset gets an empty pop up rather than an underflow.
+        let x = (f.size().left() + POPUP_OFFSET).min(f.size().right());
+        let y = (f.size().top() + POPUP_OFFSET).min(f.size().bottom());
+
+        let width = f.size().right().saturating_sub(POPUP_OFFSET);
+        let height = f.size().bottom().saturating_sub(POPUP_OFFSET);
+
+        popup_block = Rect{x, y, width, height};
+    }
+    // What's showing is only what we had before the connection went, so it's greyed out.
+    let list_style = match ui_state.offline {
+        Some(_) => Style::default().fg(Color::DarkGray),
+        None => Style::default(),
+    };
+
+    // The requests go over whichever pane is showing, so each pane breaks out rather than returning.
+    'panes: {
+        if ui_state.active == Pane::Queue {
+            let queue_table = build_queue_table(&ui_state.queue.0, ui_state.offline_queue);
+            let queue_loc = Layout::default().margin(1).constraints([Constraint::Percentage(100)]).split(size)[0];
+            f.render_stateful_widget(queue_table, queue_loc, &mut ui_state.queue.1);
+            break 'panes;
+        }
+
+        if ui_state.active == Pane::Logs {
+            let logs_loc = Layout::default().margin(1).constraints([Constraint::Percentage(100)]).split(size)[0];
+            f.render_widget(build_app_logs_element(&ui_state.log_pane, logs_loc.height), logs_loc);
+            break 'panes;
+        }
+
+        // The add-ons take over the whole screen rather than squeezing in with the other panes.
+        if matches!(ui_state.active, Pane::Addons | Pane::PopUp(PopUpPane::Addons)) {
+            let addons_table = build_addons_table(&ui_state.addons.0, &ui_state.addon_status).style(list_style);
+            let addons_loc = Layout::default().margin(1).constraints([Constraint::Percentage(100)]).split(size)[0];
+            f.render_stateful_widget(addons_table, addons_loc, &mut ui_state.addons.1);
+
+            if ui_state.active == Pane::PopUp(PopUpPane::Addons) {
+                debug!("Rendering the logs for the selected add-on");
+                let logs_loc = popup_block.intersection(size);
+                let name = ui_state
+                    .addons
+                    .1
+                    .selected()
+                    .and_then(|idx| ui_state.addons.0.get(idx))
+                    .map(|addon| addon.name.clone())
+                    .unwrap_or_default();
+                let logs = build_addon_logs_element(&name, &ui_state.addon_logs, logs_loc.height);
+                f.render_widget(widgets::Clear, logs_loc);
+                f.render_widget(logs, logs_loc);
+            }
+            break 'panes;
+        }
+
+        if matches!(ui_state.active, Pane::Backups | Pane::PopUp(PopUpPane::Backups)) {
+            let backups_table = build_backups_table(&ui_state.backups.0, &ui_state.backup_status).style(list_style);
+            let backups_loc = Layout::default().margin(1).constraints([Constraint::Percentage(100)]).split(size)[0];
+            f.render_stateful_widget(backups_table, backups_loc, &mut ui_state.backups.1);
+
+            if ui_state.active == Pane::PopUp(PopUpPane::Backups) {
+                let name_loc = Rect { height: 3, ..popup_block.intersection(size) };
+                let name_input = Paragraph::new(ui_state.input_pane.clone()).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Name for the new full backup (Enter to create, Esc to cancel)"),
+                );
+                f.render_widget(widgets::Clear, name_loc);
+                f.render_widget(name_input, name_loc);
             }
-            // We want to draw the pop up after everything else so it looks pretty
-            match lock_state.active {
+            break 'panes;
+        }
+
+        if ui_state.active == Pane::Energy {
+            draw_energy(f, size, &ui_state.energy, ui_state.energy_period, &ui_state.energy_status);
+            break 'panes;
+        }
+
+        let locs = chunks.split(size);
+        let event_list_element = List::new(event_list_items)
+            .style(list_style)
+            .highlight_style(Style::default().bg(Color::Yellow))
+            .block(Block::default().title(list_title("Events", Resource::Events, ui_state)).borders(Borders::ALL));
+        f.render_stateful_widget(event_list_element, locs[0], &mut ui_state.events.1);
+
+        let services_table_element = Table::new(services_table_rows)
+            .style(list_style)
+            .highlight_style(Style::default().bg(Color::Yellow))
+            .header(Row::new(vec!["Service Name", "Service Details"]))
+            .block(Block::default().title(list_title("Services", Resource::Services, ui_state)))
+            .widths(&[
+                Constraint::Percentage(10),
+                Constraint::Percentage(90),
+            ]);
+        f.render_stateful_widget(services_table_element, locs[1], &mut ui_state.services.1);
+
+        let states_list_element = List::new(state_list_items)
+            .block(Block::default().borders(Borders::ALL).title(list_title("States", Resource::States, ui_state)))
+            .highlight_style(Style::default().bg(Color::Yellow))
+            .style(list_style);
+        f.render_stateful_widget(states_list_element, locs[2], &mut ui_state.states.1);
+
+        // We want to draw the pop up after everything else so it looks pretty. If what it's for has
+        // gone (IE: a refresh emptied the list) it's left off rather than drawn over nothing.
+        'popup: {
+            match ui_state.active {
                 Pane::PopUp(PopUpPane::Events) => {
                     debug!("Rendering a pop up for events over the rest of the windows");
-                    let event_loc = lock_state.events.1.selected().unwrap();
-                    let passing_event = lock_state.events.0.get(event_loc).unwrap();
+                    let Some(passing_event) = selected(&ui_state.events.0, ui_state.events.1.selected()) else {
+                        break 'popup;
+                    };
                     let (popup_list, mut popup_state) = build_event_element(passing_event);
                     f.render_widget(widgets::Clear, popup_block);
                     f.render_stateful_widget(popup_list, popup_block, &mut popup_state);
@@ -158,71 +380,140 @@ pub fn draw_ui(state: &mut Arc<Mutex<UiState>>, convar: &mut Arc<Condvar>) {
                     debug!("Rendering a pop up for states over the rest of the windows");
                     /*
                     // Building the block & then table
-                    let states_loc = lock_state.states.1.selected().unwrap();
-                    let passing_states = lock_state.states.0.get(states_loc).unwrap();
+                    let states_loc = ui_state.states.1.selected().unwrap();
+                    let passing_states = ui_state.states.0.get(states_loc).unwrap();
                     let popup = StatesPopUpElement::new(popup_block, passing_states);
                     f.render_widget(widgets::Clear, popup_block);
                     f.render_stateful_widget(popup_list, popup.popup_loc, &mut popup_state);
                     */
-                    let states_loc = lock_state.states.1.selected().unwrap();
-                    let passing_states = lock_state.states.0.get(states_loc).unwrap();
+                    let Some(passing_states) = selected(&ui_state.states.0, ui_state.states.1.selected()) else {
+                        break 'popup;
+                    };
                     let popup = StatesPopUpElement::new(popup_block, passing_states);
                     let (popup_table, mut popup_state) = popup.build_table_element();
                     let screen_locs = popup.build_popup();
 
                     f.render_widget(widgets::Clear, popup_block);
-                    
+            
                     // Building the table
                     f.render_stateful_widget(popup_table, screen_locs[1], &mut popup_state);
 
                     // Building the text input
-                    let text = Paragraph::new(lock_state.input_pane.0.clone());
+                    let text = Paragraph::new(ui_state.input_pane.clone());
                     f.render_widget(text, screen_locs[2]);
 
-                    
+            
                 },
                 Pane::PopUp(PopUpPane::Services) => {
                     debug!("Rendering a pop up for services over the rest of the windows");
-                    let services_loc = lock_state.services.1.selected().unwrap();
-                    let passing_service = lock_state.services.0.get(services_loc).unwrap().clone();
+                    let Some(passing_service) = selected(&ui_state.services.0, ui_state.services.1.selected()).cloned() else {
+                        break 'popup;
+                    };
                     // I think what's happening with the UI is that I'm creating a new state each
                     // paint. I think this is why it's happening. 
                     let popup = ServicesPopUpElement::new(popup_block, &passing_service);
                     let (popup_table, _) = popup.build_table_element();
                     let screen_locs = popup.build_popup();
-                    //lock_state.services_popup = (passing_service.clone(), popup_state);
+                    //ui_state.services_popup = (passing_service.clone(), popup_state);
 
                     f.render_widget(widgets::Clear, popup_block);
-                    debug!{"painting_ui:service_popup_selected:\t{:?}", lock_state.services_popup.1.selected()};
-                    f.render_stateful_widget(popup_table, screen_locs[1], &mut lock_state.services_popup.1);
-                    let text = Paragraph::new(lock_state.input_pane.0.clone());
+                    debug!{"painting_ui:service_popup_selected:\t{:?}", ui_state.services_popup.1.selected()};
+                    f.render_stateful_widget(popup_table, screen_locs[1], &mut ui_state.services_popup.1);
+                    let text = Paragraph::new(ui_state.input_pane.clone());
                     f.render_widget(text, screen_locs[2]);
                 },
-                _ => debug!("Not building a pop up as it's not marked as active. Current active pane: {:?}", lock_state.active),
-            };
-        }).expect("Failed to draw the terminal UI");
-    };
+                _ => debug!("Not building a pop up as it's not marked as active. Current active pane: {:?}", ui_state.active),
+            }
+        }
+    }
 
-    'ui_loop: loop {
-        if convar.wait(state.lock().unwrap()).unwrap().active == Pane::None {
-            info!("Quitting since we were told to");
-            break 'ui_loop;
-        } else {
-            debug!("Repainting the UI");
-            paint_ui();
+    if let Some(offline) = &ui_state.offline {
+        draw_offline(f, size, offline, ui_state.queue.0.len());
+    }
+    draw_requests(f, size, &ui_state.requests);
+    if ui_state.quit_armed {
+        draw_quit_prompt(f, size, ui_state.queue.0.len());
+    }
+}
+
+/// What's selected in `list`, if there's still anything there.
+fn selected<T>(list: &[T], selected: Option<usize>) -> Option<&T> {
+    selected.and_then(|idx| list.get(idx))
+}
+
+/// The name of the list, marked stale while it's still what came out of the cache.
+fn list_title(name: &str, resource: Resource, ui_state: &UiState) -> String {
+    match ui_state.cached_at {
+        Some(saved) if ui_state.stale.contains(&resource) => {
+            format!("{} (stale, cached {})", name, saved.with_timezone(&Local).format("%Y-%m-%d %H:%M"))
         }
+        _ => name.to_string(),
     }
+}
 
-    disable_raw_mode().expect("couldn't disable raw mode");
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture,
-    )
-    .expect("Couldn't close everything out");
+/// A banner across the top saying Home Assistant can't be reached & when it's next tried.
+fn draw_offline<B: tui::backend::Backend>(f: &mut tui::Frame<B>, size: Rect, offline: &Offline, queued: usize) {
+    let retry = match offline.retry_at.checked_duration_since(Instant::now()) {
+        Some(wait) => format!("retrying in {}s", wait.as_millis().div_ceil(1000)),
+        None => String::from("retrying now"),
+    };
+    let mut text = format!(" offline for {}s — {}", offline.since.elapsed().as_secs(), retry);
+    if queued > 0 {
+        text.push_str(&format!(" | {} queued, Ctrl+o to see them", queued));
+    }
+    text.push_str(&format!(" ({})", offline.error));
+    let banner = Paragraph::new(text).style(Style::default().bg(Color::Red).fg(Color::White));
+    f.render_widget(banner, Rect { height: 1.min(size.height), ..size });
+}
+
+/// Asks before quitting drops what's queued, across the bottom over whatever else is showing.
+fn draw_quit_prompt<B: tui::backend::Backend>(f: &mut tui::Frame<B>, size: Rect, queued: usize) {
+    // What to press first, a narrow terminal cuts the end off.
+    let text = format!(" q again to quit & drop {} queued changes, any other key to stay", queued);
+    let prompt = Paragraph::new(text).style(Style::default().bg(Color::Yellow).fg(Color::Black));
+    let height = 1.min(size.height);
+    f.render_widget(widgets::Clear, Rect { y: size.bottom() - height, height, ..size });
+    f.render_widget(prompt, Rect { y: size.bottom() - height, height, ..size });
+}
+
+/// The changes on their way & how the ones that finished went, in the bottom right corner over
+/// whatever else is showing. Pending ones get a spinner, the rest a tick or a cross with what Home
+/// Assistant said.
+fn draw_requests<B: tui::backend::Backend>(f: &mut tui::Frame<B>, size: Rect, requests: &[Request]) {
+    if requests.is_empty() {
+        return;
+    }
+    let shown = &requests[requests.len().saturating_sub(REQUESTS_SHOWN)..];
+    let lines: Vec<Spans> = shown
+        .iter()
+        .map(|request| {
+            let (text, color) = match &request.outcome {
+                None => {
+                    let frame = (request.started.elapsed().as_millis() / SPINNER_FRAME.as_millis()) as usize;
+                    (format!("{} {}", SPINNER[frame % SPINNER.len()], request.description), Color::Yellow)
+                }
+                Some((_, Ok(answer))) => (format!("✓ {}", answer), Color::Green),
+                Some((_, Err(e))) => (format!("✗ {}", e), Color::Red),
+            };
+            Spans::from(Span::styled(text, Style::default().fg(color)))
+        })
+        .collect();
+
+    let longest = lines.iter().map(Spans::width).max().unwrap_or(0) as u16;
+    let width = (longest + 2).max(20).min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height);
+    let loc = Rect {
+        x: size.right() - width,
+        y: size.bottom() - height,
+        width,
+        height,
+    };
+    let toasts = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Requests"));
+    f.render_widget(widgets::Clear, loc);
+    f.render_widget(toasts, loc);
 }
 
-fn build_event_element(event: &'_ HAEvent) -> (List, ListState) {
+fn build_event_element(event: &'_ HAEvent) -> (List<'_>, ListState) {
     let event_list_items: Vec<_> = vec![ListItem::new(Spans::from(vec![Span::styled(
         Cow::Owned(event.listener_count.to_string()),
         Style::default(),
@@ -236,3 +527,225 @@ fn build_event_element(event: &'_ HAEvent) -> (List, ListState) {
     ret_list_state.select(Some(0));
     (ret_list, ret_list_state)
 }
+
+fn build_addons_table(addons: &[Addon], status: &str) -> Table<'static> {
+    let rows: Vec<_> = addons
+        .iter()
+        .map(|addon| {
+            let update = match (addon.update_available, &addon.version_latest) {
+                (true, Some(latest)) => format!("{} available", latest),
+                _ => String::new(),
+            };
+            Row::new(vec![
+                Cell::from(addon.name.clone()),
+                Cell::from(addon.state.clone().unwrap_or_else(|| String::from("not installed"))),
+                Cell::from(addon.version.clone().unwrap_or_default()),
+                Cell::from(update).style(Style::default().fg(Color::Green)),
+            ])
+        })
+        .collect();
+    let title = if status.is_empty() {
+        String::from("Add-ons")
+    } else {
+        format!("Add-ons | {}", status)
+    };
+    Table::new(rows)
+        .style(Style::default())
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
+        .header(Row::new(vec!["Name", "State", "Version", "Update"]))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&[
+            Constraint::Percentage(40),
+            Constraint::Percentage(15),
+            Constraint::Percentage(20),
+            Constraint::Percentage(25),
+        ])
+}
+
+fn build_backups_table(backups: &[Backup], status: &str) -> Table<'static> {
+    let rows: Vec<_> = backups
+        .iter()
+        .map(|backup| {
+            Row::new(vec![
+                Cell::from(backup.name.clone()),
+                Cell::from(backup.date.format("%Y-%m-%d %H:%M").to_string()),
+                Cell::from(backup.kind.clone()),
+                Cell::from(format!("{:.1} MB", backup.size)),
+                Cell::from(if backup.protected { "yes" } else { "no" }),
+            ])
+        })
+        .collect();
+    let title = if status.is_empty() {
+        String::from("Backups | n: new, d: download, Del: delete")
+    } else {
+        format!("Backups | {}", status)
+    };
+    Table::new(rows)
+        .style(Style::default())
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
+        .header(Row::new(vec!["Name", "Date", "Type", "Size", "Protected"]))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&[
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+            Constraint::Percentage(10),
+            Constraint::Percentage(15),
+            Constraint::Percentage(15),
+        ])
+}
+
+/// The changes waiting for Home Assistant to come back, oldest first.
+fn build_queue_table(queue: &[Queued], max_age: Option<Duration>) -> Table<'static> {
+    let rows: Vec<_> = queue
+        .iter()
+        .map(|queued| {
+            Row::new(vec![
+                Cell::from(format!("{}s ago", queued.queued.elapsed().as_secs())),
+                Cell::from(queued.change.describe()),
+            ])
+        })
+        .collect();
+    let title = match max_age {
+        Some(max_age) => format!(
+            "Queued while offline ({}) | sent once Home Assistant is back, dropped after {}s | d/Del: cancel, Ctrl+o/Esc: close",
+            queue.len(),
+            max_age.as_secs()
+        ),
+        None => String::from(
+            "Queued while offline | queue_offline is off, changes made offline are sent straight away | Ctrl+o/Esc: close",
+        ),
+    };
+    Table::new(rows)
+        .style(Style::default())
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
+        .header(Row::new(vec!["Queued", "Change"]))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&[Constraint::Percentage(15), Constraint::Percentage(85)])
+}
+
+/// Draws the energy pane, the totals up top, then grid import over time, then each device's use.
+/// The bars are in Wh since they can only show whole numbers.
+fn draw_energy<B: tui::backend::Backend>(
+    f: &mut tui::Frame<B>,
+    size: Rect,
+    energy: &EnergySummary,
+    period: StatisticsPeriod,
+    status: &str,
+) {
+    let locs = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .split(size);
+
+    let totals = format!(
+        "Grid import {:.2} kWh | Grid export {:.2} kWh | Solar {:.2} kWh",
+        energy.grid_import_total, energy.grid_export_total, energy.solar_total
+    );
+    let title = match status.is_empty() {
+        true => format!("Energy | {:?} | h/d/w/m: change period", period),
+        false => format!("Energy | {:?} | {}", period, status),
+    };
+    f.render_widget(
+        Paragraph::new(totals).block(Block::default().borders(Borders::ALL).title(title)),
+        locs[0],
+    );
+
+    let label_format = match period {
+        StatisticsPeriod::FiveMinute => "%M",
+        StatisticsPeriod::Hour => "%H",
+        StatisticsPeriod::Day | StatisticsPeriod::Week => "%d",
+        StatisticsPeriod::Month => "%b",
+    };
+    let import_bars: Vec<(String, u64)> = energy
+        .grid_import
+        .iter()
+        .map(|(start, kwh)| {
+            let label = start.with_timezone(&Local).format(label_format).to_string();
+            (label, (kwh * 1000.0).round().max(0.0) as u64)
+        })
+        .collect();
+    let import_data: Vec<(&str, u64)> = import_bars.iter().map(|(label, wh)| (label.as_str(), *wh)).collect();
+    f.render_widget(
+        BarChart::default()
+            .block(Block::default().borders(Borders::ALL).title("Grid import (Wh)"))
+            .data(&import_data)
+            .bar_width(4)
+            .bar_style(Style::default().fg(Color::Yellow))
+            .value_style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+        locs[1],
+    );
+
+    let device_bars: Vec<(String, u64)> = energy
+        .devices
+        .iter()
+        .map(|(name, kwh)| (name.clone(), (kwh * 1000.0).round().max(0.0) as u64))
+        .collect();
+    let device_data: Vec<(&str, u64)> = device_bars.iter().map(|(label, wh)| (label.as_str(), *wh)).collect();
+    f.render_widget(
+        BarChart::default()
+            .block(Block::default().borders(Borders::ALL).title("Devices (Wh)"))
+            .data(&device_data)
+            .bar_width(10)
+            .bar_style(Style::default().fg(Color::Green))
+            .value_style(Style::default().fg(Color::Black).bg(Color::Green)),
+        locs[2],
+    );
+}
+
+/// The logs are scrolled so the newest line sits at the bottom, `logs.1` lines back up from there.
+fn build_addon_logs_element(name: &str, logs: &(String, u16), height: u16) -> Paragraph<'static> {
+    let line_count = u16::try_from(logs.0.lines().count()).unwrap_or(u16::MAX);
+    let scroll = line_count
+        .saturating_sub(height.saturating_sub(2))
+        .saturating_sub(logs.1);
+    Paragraph::new(logs.0.clone())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{} logs", name)),
+        )
+        .scroll((scroll, 0))
+}
+
+/// haoscli's own recent log lines at the pane's level or more important, newest at the bottom.
+fn build_app_logs_element(pane: &LogPane, height: u16) -> Paragraph<'static> {
+    let lines: Vec<Spans> = logging::recent(pane.level)
+        .into_iter()
+        .map(|line| {
+            let color = match line.level {
+                Level::Error => Color::Red,
+                Level::Warn => Color::Yellow,
+                Level::Info => Color::Reset,
+                Level::Debug | Level::Trace => Color::DarkGray,
+            };
+            Spans::from(vec![
+                Span::raw(format!("{} ", line.time.format("%H:%M:%S"))),
+                Span::styled(format!("{:<5} ", line.level), Style::default().fg(color)),
+                Span::styled(format!("{}: ", line.target), Style::default().fg(Color::DarkGray)),
+                Span::raw(line.message),
+            ])
+        })
+        .collect();
+    let line_count = u16::try_from(lines.len()).unwrap_or(u16::MAX);
+    let scroll = line_count
+        .saturating_sub(height.saturating_sub(2))
+        .saturating_sub(pane.scroll);
+    let file = match logging::log_path() {
+        Some(path) => path.display().to_string(),
+        None => String::from("not written to a file"),
+    };
+    Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Logs ({}) | {} & up | e/w/i/d/t: change level, Ctrl+l/Esc: close",
+            file, pane.level
+        )))
+        .scroll((scroll, 0))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,122 @@
+//! Renders the UI onto a `TestBackend` & compares what ends up on screen with the snapshots next to
+//! this file. After changing how something looks, `UPDATE_EXPECT=1 cargo test` rewrites them.
+use std::time::Instant;
+
+use expect_test::{expect_file, ExpectFile};
+use serde_json::json;
+use tui::{backend::TestBackend, Terminal};
+
+use haoscli::types::{AddonAction, Event, Service, State};
+
+use super::paint;
+use crate::actions::Change;
+use crate::ui_types::{Pane, PopUpPane, Queued, UiState};
+
+/// Tiny, small, the usual 80x24 & a roomy one.
+const SIZES: [(u16, u16); 4] = [(4, 3), (48, 16), (80, 24), (120, 36)];
+
+fn fixture() -> UiState {
+    let mut state = UiState::default();
+    state.events.0 = serde_json::from_value::<Vec<Event>>(json!([
+        {"event": "state_changed", "listener_count": 3},
+        {"event": "call_service", "listener_count": 1},
+        {"event": "automation_triggered", "listener_count": 2},
+    ]))
+    .unwrap();
+    state.services.0 = serde_json::from_value::<Vec<Service>>(json!([
+        {"domain": "light", "services": {
+            "turn_on": {"description": "Turn on one or more lights", "fields": {"brightness": {}}},
+            "turn_off": {"description": "Turn off one or more lights"},
+        }},
+        {"domain": "switch", "services": {"toggle": {"description": "Toggle a switch"}}},
+    ]))
+    .unwrap();
+    state.states.0 = serde_json::from_value::<Vec<State>>(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00",
+            "attributes": {"brightness": 120, "friendly_name": "Desk"}},
+        {"entity_id": "sensor.temperature", "state": "21.5", "last_changed": "2024-01-01T09:30:00+00:00",
+            "attributes": {"unit_of_measurement": "°C"}},
+    ]))
+    .unwrap();
+    state.events.1.select(Some(0));
+    state.services.1.select(Some(0));
+    state.states.1.select(Some(0));
+    state.active = Pane::Events;
+    state
+}
+
+/// Paints `state` at each of the sizes, one after the other with the size above each.
+fn screens(mut state: UiState) -> String {
+    let mut screens = String::new();
+    for (width, height) in SIZES {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        paint(&mut terminal, &mut state);
+        let buffer = terminal.backend().buffer();
+        screens.push_str(&format!("{}x{}\n", width, height));
+        for y in 0..height {
+            let line: String = (0..width).map(|x| buffer.get(x, y).symbol.as_str()).collect();
+            screens.push_str(line.trim_end());
+            screens.push('\n');
+        }
+        screens.push('\n');
+    }
+    screens
+}
+
+fn check(state: UiState, snapshot: ExpectFile) {
+    snapshot.assert_eq(&screens(state));
+}
+
+#[test]
+fn main_layout() {
+    check(fixture(), expect_file!["snapshots/main_layout.txt"]);
+}
+
+#[test]
+fn events_popup() {
+    let mut state = fixture();
+    state.active = Pane::PopUp(PopUpPane::Events);
+    check(state, expect_file!["snapshots/events_popup.txt"]);
+}
+
+#[test]
+fn states_popup() {
+    let mut state = fixture();
+    state.active = Pane::PopUp(PopUpPane::States);
+    state.input_pane = String::from(r#"{"state": "off"}"#);
+    check(state, expect_file!["snapshots/states_popup.txt"]);
+}
+
+#[test]
+fn services_popup() {
+    let mut state = fixture();
+    state.active = Pane::PopUp(PopUpPane::Services);
+    state.services_popup.0 = state.services.0[0].clone();
+    state.services_popup.1.select(Some(0));
+    state.input_pane = String::from(r#"{"entity_id": "light.desk"}"#);
+    check(state, expect_file!["snapshots/services_popup.txt"]);
+}
+
+#[test]
+fn quit_prompt() {
+    let mut state = fixture();
+    for id in 0..2 {
+        state.queue.0.push(Queued {
+            id,
+            change: Change::Addon(String::from("core_mosquitto"), AddonAction::Restart),
+            queued: Instant::now(),
+        });
+    }
+    state.quit_armed = true;
+    check(state, expect_file!["snapshots/quit_prompt.txt"]);
+}
+
+#[test]
+fn popup_over_an_emptied_list() {
+    let mut state = fixture();
+    state.active = Pane::PopUp(PopUpPane::States);
+    state.states.0.clear();
+    state.states.1.select(None);
+    // Left off, rather than panicking over nothing being selected.
+    assert!(!screens(state).contains("light.desk"));
+}
@@ -1,14 +1,23 @@
 use tui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
-    widgets::{Block, Borders, Cell, List, ListState, Row, Table, TableState},
+    widgets::{Block, Borders, Cell, ListState, Row, Table, TableState},
 };
 
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use haoscli::types::Event as HAEvent;
 
-use haoscli::types::{Service, State};
+use haoscli::types::{Addon, Backup, Service, State, StatisticsPeriod};
+
+use chrono::{DateTime, Utc};
+
+use log::{info, LevelFilter};
+
+use crate::actions::{Change, Command, Quit, RequestId, Resource};
 
 /// Enum to determine which pane is currently the active pane.
 #[derive(PartialEq, Debug, Default, Clone)]
@@ -17,8 +26,14 @@ pub enum Pane {
     Events,
     Services,
     States,
+    Addons,
+    Backups,
+    Energy,
+    /// haoscli's own log, brought up over whatever else is showing with Ctrl+l.
+    Logs,
+    /// The changes made while offline that are waiting to be sent, brought up with Ctrl+o.
+    Queue,
     PopUp(PopUpPane),
-    Search,
     None,
 }
 
@@ -28,10 +43,123 @@ pub enum PopUpPane {
     Events,
     Services,
     States,
+    Addons,
+    Backups,
     #[default]
     None,
 }
 
+/// What the user asked to do from the backups pane.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BackupAction {
+    /// Make a full backup with the given name, the Supervisor picks one if it's empty.
+    Create(String),
+    Delete(String),
+    /// Save the backup with this slug into the current directory, see `backup_file`.
+    Download(String),
+}
+
+/// Where the UI downloads a backup to, IE: `a1b2c3d4.tar` in the current directory.
+pub fn backup_file(slug: &str) -> PathBuf {
+    PathBuf::from(format!("{}.tar", slug))
+}
+
+/// The energy dashboard boiled down to what the energy pane draws. Everything is in kWh.
+#[derive(Debug, Default, Clone)]
+pub struct EnergySummary {
+    /// The start of each bucket and how much was pulled from the grid during it.
+    pub grid_import: Vec<(DateTime<Utc>, f64)>,
+    pub grid_import_total: f64,
+    pub grid_export_total: f64,
+    pub solar_total: f64,
+    /// The name of each tracked device and how much it used over the whole window.
+    pub devices: Vec<(String, f64)>,
+}
+
+/// What the log pane is showing.
+#[derive(Debug, Clone)]
+pub struct LogPane {
+    /// The most verbose level shown, Info & up to start with.
+    pub level: LevelFilter,
+    /// How many lines up from the newest we've scrolled.
+    pub scroll: u16,
+    /// Where closing the pane goes back to.
+    pub return_to: Pane,
+}
+
+impl Default for LogPane {
+    fn default() -> Self {
+        LogPane {
+            level: LevelFilter::Info,
+            scroll: 0,
+            return_to: Pane::Events,
+        }
+    }
+}
+
+/// A change sent off from the UI. It gets a spinner until it's answered & then sticks around for a
+/// bit saying how it went.
+#[derive(Debug)]
+pub struct Request {
+    pub id: RequestId,
+    pub description: String,
+    /// Kept so it can be queued again if the connection goes while it's being sent.
+    pub change: Change,
+    pub started: Instant,
+    /// When it was first queued while offline, if it was. Queueing it again keeps this so it still
+    /// ages out after `queue_max_age`.
+    pub queued: Option<Instant>,
+    /// When it was answered and what with, the error is already described.
+    pub outcome: Option<(Instant, Result<String, String>)>,
+}
+
+impl Request {
+    pub fn new(id: RequestId, change: Change) -> Self {
+        Request {
+            id,
+            description: change.describe(),
+            change,
+            started: Instant::now(),
+            queued: None,
+            outcome: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.outcome.is_none()
+    }
+}
+
+/// A state that's showing what a change said it did, ahead of a fetch confirming it.
+#[derive(Debug)]
+pub struct Unconfirmed {
+    pub expected: State,
+    /// What the last fetch had under it, `None` if it didn't have the entity at all. This is what
+    /// the cache gets, a change that's rolled back shouldn't come back on the next launch.
+    pub fetched: Option<State>,
+    /// When the change was answered, fetches sent before then can't confirm anything.
+    pub since: Instant,
+    pub change: Change,
+}
+
+/// A change made while offline, sent once Home Assistant is back unless it's been waiting too long.
+#[derive(Debug)]
+pub struct Queued {
+    pub id: RequestId,
+    pub change: Change,
+    pub queued: Instant,
+}
+
+/// Home Assistant can't be reached, IE: it's restarting. Only a single fetch goes out at `retry_at`,
+/// backing off a little more each time it fails.
+#[derive(Debug)]
+pub struct Offline {
+    pub since: Instant,
+    pub attempt: u32,
+    pub retry_at: Instant,
+    pub error: String,
+}
+
 /// Struct which holds the state of the UI. For each pane, there is the associated data and then,
 /// assuming that the widget is stateful, the state for that widget.
 #[derive(Debug, Default)]
@@ -39,7 +167,6 @@ pub struct UiState {
     pub active: Pane,
 
     pub events: (Vec<HAEvent>, ListState),
-    pub events_popop: (HAEvent, ListState),
 
     pub services: (Vec<Service>, TableState),
     pub services_popup: (Service, TableState),
@@ -47,11 +174,58 @@ pub struct UiState {
     pub services_popup_selected: String, 
 
     pub states: (Vec<State>, ListState),
-    pub states_popup: (State, TableState),
-
 
-    pub search: String,
-    pub input_pane: (String, bool),    // This should really be a struct, ideally, each "pop up"
+    pub addons: (Vec<Addon>, TableState),
+    /// The logs for the selected add-on and how many lines up from the bottom we've scrolled.
+    pub addon_logs: (String, u16),
+    /// The outcome of the last add-on action, shown in the title of the add-ons pane.
+    pub addon_status: String,
+
+    pub backups: (Vec<Backup>, TableState),
+    /// The slug of the backup delete was pressed on once, pressing it again deletes it.
+    pub backup_delete_armed: Option<String>,
+    /// The slug of the backup download was pressed on once while its file was already there,
+    /// pressing it again overwrites the file.
+    pub backup_overwrite_armed: Option<String>,
+    /// The outcome of the last backup action, shown in the title of the backups pane.
+    pub backup_status: String,
+
+    pub energy: EnergySummary,
+    /// How the energy pane buckets its bars, the fetcher refetches when this changes.
+    pub energy_period: StatisticsPeriod,
+    pub energy_status: String,
+    /// Which period the energy statistics showing are for.
+    pub energy_fetched: Option<StatisticsPeriod>,
+
+    pub log_pane: LogPane,
+
+    /// The polls that have been sent & not answered yet, & when they were sent.
+    pub fetching: HashMap<Resource, Instant>,
+    /// How often each resource is fetched again & when it last was.
+    pub intervals: HashMap<Resource, Duration>,
+    pub fetched: HashMap<Resource, Instant>,
+    /// The lists still showing what was in the cache at startup, & when that was saved.
+    pub stale: HashSet<Resource>,
+    pub cached_at: Option<DateTime<Utc>>,
+    pub offline: Option<Offline>,
+    /// How long a change made while offline is kept to be sent once it's back, `None` if they're
+    /// sent straight away (& fail) like when online.
+    pub offline_queue: Option<Duration>,
+    pub queue: (Vec<Queued>, TableState),
+    /// Where Esc or Ctrl+o goes back to from the queue.
+    pub queue_return_to: Pane,
+    /// The changes on their way & the ones that finished recently, oldest first.
+    pub requests: Vec<Request>,
+    /// The states a change said it left behind, by entity id, shown until a fetch agrees or not.
+    pub unconfirmed: HashMap<String, Unconfirmed>,
+    pub next_request_id: RequestId,
+    /// Set once something's asked the UI to close, the pane goes to `Pane::None` at the same time.
+    pub quitting: Option<Quit>,
+    /// q (or Ctrl+c) was pressed with changes still queued, pressing it again drops them & quits,
+    /// any other key keeps the UI open.
+    pub quit_armed: bool,
+
+    pub input_pane: String,            // This should really be a struct, ideally, each "pop up"
                                        // should manage it's search state via a more complex struct
                                        // and a trait that allows for input to it/resetting it.
                                        // Should make code more uniform. Honestly, I don't think I
@@ -63,27 +237,71 @@ impl UiState {
         let selected_service = self.services.1.selected().unwrap();
         self.services.0.get(selected_service).unwrap()
     }
-}
 
-pub trait BuildPopup {
-    fn set_loc(&mut self, popup_loc: Rect);
+    pub fn quit(&mut self, quit: Quit) {
+        info!("Quitting ({:?})", quit);
+        self.quitting = Some(quit);
+        self.active = Pane::None;
+    }
 
-    fn build_popup(&self) -> Vec<Rect>;
-}
+    /// Whether a pop up that takes typing is open. Nothing's polled while it is so the list doesn't
+    /// move out from under it.
+    pub fn editing(&self) -> bool {
+        matches!(
+            self.active,
+            Pane::PopUp(PopUpPane::Events)
+                | Pane::PopUp(PopUpPane::States)
+                | Pane::PopUp(PopUpPane::Services)
+                | Pane::PopUp(PopUpPane::Backups)
+        )
+    }
 
-pub trait BuildList {
-    fn build_list_element(&self) -> (Box<List>, Box<ListState>);
-}
+    /// The states as they were last fetched, without the changes still waiting to be confirmed.
+    pub fn confirmed_states(&self) -> Vec<State> {
+        self.states
+            .0
+            .iter()
+            .filter_map(|state| match self.unconfirmed.get(&state.entity_id) {
+                Some(unconfirmed) => unconfirmed.fetched.clone(),
+                None => Some(state.clone()),
+            })
+            .collect()
+    }
 
-pub trait BuildTable {
-    fn build_table_element(&self) -> (Table, TableState);
+    /// Shows how something turned out without sending anything, IE: a change that didn't stick.
+    pub fn notify(&mut self, change: Change, outcome: Result<String, String>) {
+        let mut request = Request::new(self.next_request_id, change);
+        self.next_request_id += 1;
+        request.outcome = Some((Instant::now(), outcome));
+        self.requests.push(request);
+    }
+
+    /// Gives the change an id & shows it as pending, the command that comes back sends it off.
+    /// While offline it's queued instead, if that's turned on.
+    pub fn send(&mut self, change: Change) -> Vec<Command> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        if self.offline.is_some() && self.offline_queue.is_some() {
+            info!("Queued {} until Home Assistant is back", change.describe());
+            self.queue.0.push(Queued {
+                id,
+                change,
+                queued: Instant::now(),
+            });
+            return Vec::new();
+        }
+        self.requests.push(Request::new(id, change.clone()));
+        vec![Command::Send(id, change)]
+    }
 }
 
-pub struct EventsPopUpElement {
-    popup_loc: Rect,
+pub trait BuildPopup {
+    fn build_popup(&self) -> Vec<Rect>;
 }
 
-impl EventsPopUpElement {}
+pub trait BuildTable {
+    fn build_table_element(&self) -> (Table<'_>, TableState);
+}
 
 pub struct ServicesPopUpElement<'popup> {
     popup_loc: Rect,
@@ -97,10 +315,6 @@ impl<'popup> ServicesPopUpElement<'popup> {
 }
 
 impl<'popup> BuildPopup for ServicesPopUpElement<'popup> {
-    fn set_loc(&mut self, popup_loc: Rect) {
-        self.popup_loc = popup_loc;
-    }
-
     fn build_popup(&self) -> Vec<Rect> {
         Layout::default()
             .direction(Direction::Vertical)
@@ -115,7 +329,7 @@ impl<'popup> BuildPopup for ServicesPopUpElement<'popup> {
 }
 
 impl<'popup> BuildTable for ServicesPopUpElement<'popup> {
-    fn build_table_element(&self) -> (Table, TableState) {
+    fn build_table_element(&self) -> (Table<'_>, TableState) {
         let value_map = self
             .service
             .services
@@ -158,10 +372,6 @@ impl<'popup> StatesPopUpElement<'popup> {
 }
 
 impl<'popup> BuildPopup for StatesPopUpElement<'popup> {
-    fn set_loc(&mut self, popup_loc: Rect) {
-        self.popup_loc = popup_loc
-    }
-
     fn build_popup(&self) -> Vec<Rect> {
         Layout::default()
             .direction(Direction::Vertical)
@@ -176,7 +386,7 @@ impl<'popup> BuildPopup for StatesPopUpElement<'popup> {
 }
 
 impl<'popup> BuildTable for StatesPopUpElement<'popup> {
-    fn build_table_element(&self) -> (Table, TableState) {
+    fn build_table_element(&self) -> (Table<'_>, TableState) {
         let states_table_rows: Vec<Row> = vec![Row::new(vec![
             Cell::from(Cow::Owned(self.state.state.to_string())),
             Cell::from(Cow::Owned(self.state.last_changed.to_string())),
@@ -0,0 +1,181 @@
+//! `haoscli watch`: prints one json object per line for every state change (or event) until it's
+//! killed. Goes through a websocket subscription when it can, otherwise it polls `/api/states` and
+//! works out what changed itself.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::ArgMatches;
+#[cfg(feature = "websocket")]
+use log::{info, warn};
+use serde_json::json;
+
+use haoscli::types::{HomeAssistantConnection, State};
+#[cfg(feature = "websocket")]
+use haoscli::websocket::HomeAssistantWebSocket;
+
+use crate::cli::EntityFilter;
+
+const STATE_CHANGED: &str = "state_changed";
+/// How long to wait before reopening a websocket that dropped, doubled each time it still won't
+/// open up to `RECONNECT_AT_MOST`.
+#[cfg(feature = "websocket")]
+const RECONNECT_FIRST: Duration = Duration::from_secs(1);
+#[cfg(feature = "websocket")]
+const RECONNECT_AT_MOST: Duration = Duration::from_secs(30);
+/// How long to keep trying before giving up & exiting, IE: the token was revoked rather than
+/// Home Assistant restarting.
+#[cfg(feature = "websocket")]
+const RECONNECT_FOR: Duration = Duration::from_secs(300);
+
+pub async fn run(haos_conn: &HomeAssistantConnection, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let filter = EntityFilter::from_matches(matches);
+    let event_type = matches
+        .get_one::<String>("event")
+        .map(String::as_str)
+        .unwrap_or(STATE_CHANGED);
+    let interval = Duration::from_secs(*matches.get_one::<u64>("interval").unwrap_or(&5));
+
+    #[cfg(feature = "websocket")]
+    match haos_conn.connect_websocket().await {
+        Ok(websocket) => return follow(haos_conn, websocket, event_type, &filter).await,
+        Err(e) => {
+            warn!("Couldn't open the websocket, falling back to polling: {}", e);
+            eprintln!("Couldn't open the websocket ({}), polling every {:?} instead", e, interval);
+        }
+    }
+
+    if event_type != STATE_CHANGED {
+        return Err(format!("Watching {} needs the websocket, only state changes can be polled", event_type).into());
+    }
+    poll(haos_conn, &filter, interval).await
+}
+
+/// Prints the subscription's events as they come. If the websocket drops, IE: Home Assistant
+/// restarting, it's opened again & the subscription made afresh. Anything fired in between is missed.
+#[cfg(feature = "websocket")]
+async fn follow(
+    haos_conn: &HomeAssistantConnection,
+    mut websocket: HomeAssistantWebSocket,
+    event_type: &str,
+    filter: &EntityFilter,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let dropped = match websocket.subscribe_events(Some(event_type)).await {
+            Ok(()) => {
+                info!("Watching {} over the websocket", event_type);
+                loop {
+                    match websocket.next_event().await {
+                        Ok(event) if wanted(filter, &event) => print_line(&event)?,
+                        Ok(_) => (),
+                        Err(e) => break e,
+                    }
+                }
+            }
+            Err(e) => e,
+        };
+        warn!("The websocket dropped: {}", dropped);
+        eprintln!("The websocket dropped ({}), reconnecting", dropped);
+
+        let mut wait = RECONNECT_FIRST;
+        let mut waited = Duration::ZERO;
+        websocket = loop {
+            tokio::time::sleep(wait).await;
+            waited += wait;
+            match haos_conn.connect_websocket().await {
+                Ok(websocket) => break websocket,
+                Err(e) if waited >= RECONNECT_FOR => {
+                    return Err(format!("The websocket couldn't be opened again in {:?}: {}", RECONNECT_FOR, e).into())
+                }
+                Err(e) => {
+                    warn!("Still can't open the websocket, retrying in {:?}: {}", wait, e);
+                    wait = (wait * 2).min(RECONNECT_AT_MOST);
+                }
+            }
+        };
+        eprintln!("Reconnected, anything fired while it was down was missed");
+    }
+}
+
+/// Events that aren't about an entity only get through when no filter was given.
+#[cfg(feature = "websocket")]
+fn wanted(filter: &EntityFilter, event: &serde_json::Value) -> bool {
+    filter.matches(event["data"]["entity_id"].as_str())
+}
+
+/// Diffs each poll against the last one, shaping what changed like the websocket's `state_changed`
+/// events so whatever is reading doesn't have to care which one it got.
+async fn poll(haos_conn: &HomeAssistantConnection, filter: &EntityFilter, interval: Duration) -> Result<(), Box<dyn Error>> {
+    let mut previous = to_map(haos_conn.get_states().await?);
+    loop {
+        tokio::time::sleep(interval).await;
+        let current = to_map(haos_conn.get_states().await?);
+        for line in changes(previous, &current, filter) {
+            print_line(&line)?;
+        }
+        previous = current;
+    }
+}
+
+/// What changed between two polls as `state_changed` events, by entity id. Anything in `previous`
+/// that isn't in `current` was removed.
+fn changes(
+    mut previous: BTreeMap<String, State>,
+    current: &BTreeMap<String, State>,
+    filter: &EntityFilter,
+) -> Vec<serde_json::Value> {
+    let mut lines = Vec::new();
+    for (entity_id, new_state) in current {
+        let old_state = previous.remove(entity_id);
+        let changed = match &old_state {
+            Some(old) => old.state != new_state.state || old.attributes != new_state.attributes,
+            None => true,
+        };
+        if changed && filter.matches(Some(entity_id)) {
+            lines.push(state_changed(entity_id, old_state.as_ref(), Some(new_state)));
+        }
+    }
+    for (entity_id, old_state) in previous {
+        if filter.matches(Some(&entity_id)) {
+            lines.push(state_changed(&entity_id, Some(&old_state), None));
+        }
+    }
+    lines.sort_by(|a, b| a["data"]["entity_id"].as_str().cmp(&b["data"]["entity_id"].as_str()));
+    lines
+}
+
+fn to_map(states: Vec<State>) -> BTreeMap<String, State> {
+    states
+        .into_iter()
+        .map(|state| (state.entity_id.clone(), state))
+        .collect()
+}
+
+fn state_changed(entity_id: &str, old_state: Option<&State>, new_state: Option<&State>) -> serde_json::Value {
+    json!({
+        "event_type": STATE_CHANGED,
+        "data": {
+            "entity_id": entity_id,
+            "old_state": old_state,
+            "new_state": new_state,
+        },
+        "time_fired": Utc::now().to_rfc3339(),
+        "origin": "POLL",
+    })
+}
+
+/// One line per object, flushed straight away so it can be piped into something.
+fn print_line(value: &serde_json::Value) -> io::Result<()> {
+    write_line(&mut io::stdout().lock(), value)
+}
+
+fn write_line(out: &mut impl Write, value: &serde_json::Value) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use haoscli::types::State;
+
+#[cfg(feature = "websocket")]
+use super::wanted;
+use super::{changes, to_map, write_line};
+use crate::cli::{self, EntityFilter};
+
+/// The filter `haoscli watch` would get from these arguments.
+fn filter(args: &[&str]) -> EntityFilter {
+    let watch = cli::subcommands()
+        .into_iter()
+        .find(|command| command.get_name() == "watch")
+        .unwrap();
+    let matches = watch.try_get_matches_from(std::iter::once("watch").chain(args.iter().copied())).unwrap();
+    EntityFilter::from_matches(&matches)
+}
+
+fn states(states: Value) -> BTreeMap<String, State> {
+    to_map(serde_json::from_value(states).unwrap())
+}
+
+fn before() -> BTreeMap<String, State> {
+    states(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {"brightness": 120}},
+        {"entity_id": "light.hall", "state": "off", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+        {"entity_id": "sensor.power", "state": "120", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+        {"entity_id": "switch.fan", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+    ]))
+}
+
+fn after() -> BTreeMap<String, State> {
+    states(json!([
+        {"entity_id": "light.desk", "state": "on", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {"brightness": 80}},
+        {"entity_id": "light.hall", "state": "off", "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}},
+        {"entity_id": "light.porch", "state": "on", "last_changed": "2024-01-01T10:05:00+00:00", "attributes": {}},
+        {"entity_id": "sensor.power", "state": "95", "last_changed": "2024-01-01T10:05:00+00:00", "attributes": {}},
+    ]))
+}
+
+/// What changed for each entity, as old state -> new state.
+fn summary(lines: &[Value]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| {
+            let state = |which: &str| line["data"][which]["state"].as_str().unwrap_or("-").to_string();
+            format!("{} {} -> {}", line["data"]["entity_id"].as_str().unwrap(), state("old_state"), state("new_state"))
+        })
+        .collect()
+}
+
+#[test]
+fn polls_are_shaped_like_state_changed_events() {
+    let lines = changes(before(), &after(), &filter(&[]));
+    assert_eq!(
+        summary(&lines),
+        ["light.desk on -> on", "light.porch - -> on", "sensor.power 120 -> 95", "switch.fan on -> -"]
+    );
+    assert_eq!(lines[0]["event_type"], "state_changed");
+    assert_eq!(lines[0]["origin"], "POLL");
+    assert_eq!(lines[0]["data"]["old_state"]["attributes"], json!({"brightness": 120}));
+    assert_eq!(lines[0]["data"]["new_state"]["attributes"], json!({"brightness": 80}));
+    assert_eq!(lines[3]["data"]["new_state"], Value::Null);
+    assert!(lines[0]["time_fired"].as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().is_ok());
+}
+
+#[test]
+fn polls_are_filtered() {
+    assert_eq!(
+        summary(&changes(before(), &after(), &filter(&["--domain", "light"]))),
+        ["light.desk on -> on", "light.porch - -> on"]
+    );
+    assert_eq!(
+        summary(&changes(before(), &after(), &filter(&["--entity", "*.p*"]))),
+        ["light.porch - -> on", "sensor.power 120 -> 95"]
+    );
+    assert_eq!(
+        summary(&changes(before(), &after(), &filter(&["--entity", "*.p*", "--domain", "sensor"]))),
+        ["sensor.power 120 -> 95"]
+    );
+    assert!(changes(before(), &before(), &filter(&[])).is_empty());
+}
+
+#[cfg(feature = "websocket")]
+#[test]
+fn events_are_filtered() {
+    let light = json!({"event_type": "state_changed", "data": {"entity_id": "light.desk"}});
+    let sensor = json!({"event_type": "state_changed", "data": {"entity_id": "sensor.power"}});
+    let no_entity = json!({"event_type": "homeassistant_started", "data": {}});
+
+    assert!([&light, &sensor, &no_entity].iter().all(|event| wanted(&filter(&[]), event)));
+    let lights = filter(&["--domain", "light"]);
+    assert!(wanted(&lights, &light));
+    assert!(!wanted(&lights, &sensor));
+    // Asking for entities leaves out the events that aren't about one.
+    assert!(!wanted(&lights, &no_entity));
+    assert!(wanted(&filter(&["--entity", "sensor.*"]), &sensor));
+}
+
+#[test]
+fn one_line_of_json_each() {
+    let mut out = Vec::new();
+    write_line(&mut out, &json!({"event_type": "state_changed", "data": {"entity_id": "light.desk", "note": "two\nlines"}})).unwrap();
+    write_line(&mut out, &json!({"event_type": "call_service"})).unwrap();
+
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(text.ends_with('\n'));
+    assert_eq!(serde_json::from_str::<Value>(lines[0]).unwrap()["data"]["note"], "two\nlines");
+    assert_eq!(lines[1], r#"{"event_type":"call_service"}"#);
+}
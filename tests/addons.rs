@@ -0,0 +1,78 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use haoscli::error::Error;
+use haoscli::types::AddonAction;
+
+use common::{connect, json, serve, Route};
+
+#[tokio::test]
+async fn unwraps_the_supervisor_envelope() {
+    let server = serve(vec![
+        json(
+            "GET /api/hassio/addons",
+            json!({"result": "ok", "data": {"addons": [
+                {"name": "Mosquitto broker", "slug": "core_mosquitto", "version": "6.1.3", "version_latest": "6.2.0",
+                    "update_available": true, "state": "started"},
+                {"name": "Terminal & SSH", "slug": "core_ssh", "version": null, "version_latest": "9.6.1", "state": null},
+            ]}}),
+        ),
+        json("GET /api/hassio/addons/core_ssh/info", json!({"result": "error", "message": "Addon is not installed"})),
+        json("GET /api/hassio/addons/core_dns/info", json!({"result": "ok"})),
+        json("POST /api/hassio/addons/core_mosquitto/stop", json!({"result": "ok", "data": {}})),
+        json("POST /api/hassio/addons/core_ssh/start", json!({"result": "error", "message": "Addon is not installed"})),
+        Route {
+            request: "GET /api/hassio/addons/core_mosquitto/logs",
+            status: 200,
+            content_type: "text/plain",
+            body: b"\x1b[32m1: Opening ipv4 listen socket on port 1883.\x1b[0m\n".to_vec(),
+            delay: Duration::ZERO,
+        },
+    ]);
+    let conn = connect(&server.url);
+    let conn = conn.read().unwrap().clone();
+
+    let addons = conn.get_addons().await.unwrap();
+    assert_eq!(addons.len(), 2);
+    assert_eq!((addons[0].slug.as_str(), addons[0].update_available, addons[0].state.as_deref()), ("core_mosquitto", true, Some("started")));
+    assert_eq!((addons[1].version.as_deref(), addons[1].state.as_deref()), (None, None));
+    assert!(matches!(
+        conn.get_addon_info("core_ssh").await,
+        Err(Error::Supervisor(message)) if message == "Addon is not installed"
+    ));
+    // An "ok" without the data it should have is still an error, rather than a panic.
+    assert!(matches!(conn.get_addon_info("core_dns").await, Err(Error::Supervisor(_))));
+
+    conn.addon_action("core_mosquitto", AddonAction::Stop).await.unwrap();
+    assert!(matches!(
+        conn.addon_action("core_ssh", AddonAction::Start).await,
+        Err(Error::Supervisor(message)) if message == "Addon is not installed"
+    ));
+    // Logs are plain text, the colour codes are left for the UI to strip.
+    assert_eq!(
+        conn.get_addon_logs("core_mosquitto").await.unwrap(),
+        "\x1b[32m1: Opening ipv4 listen socket on port 1883.\x1b[0m\n"
+    );
+}
+
+#[tokio::test]
+async fn slow_addon_actions_dont_look_like_the_server_went_away() {
+    let slowly = |mut route: common::Route| {
+        route.delay = Duration::from_millis(500);
+        route
+    };
+    let server = serve(vec![
+        slowly(json("POST /api/hassio/addons/core_mosquitto/restart", json!({"result": "ok", "data": {}}))),
+        slowly(json("GET /api/hassio/addons", json!({"result": "ok", "data": {"addons": []}}))),
+    ]);
+    let conn = connect(&server.url);
+    let mut conn = conn.read().unwrap().clone();
+    conn.request_timeout = Duration::from_millis(200);
+
+    conn.addon_action("core_mosquitto", AddonAction::Restart).await.unwrap();
+    // A poll that's as slow does give up, that's what tells the UI the server's gone.
+    assert!(conn.get_addons().await.unwrap_err().is_connection_error());
+}
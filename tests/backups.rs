@@ -0,0 +1,85 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use haoscli::error::Error;
+use haoscli::types::NewBackup;
+
+use common::{connect, json, scratch_dir, serve, Route};
+
+fn ok(data: serde_json::Value) -> serde_json::Value {
+    json!({"result": "ok", "data": data})
+}
+
+#[tokio::test]
+async fn lists_creates_and_deletes() {
+    let server = serve(vec![
+        json(
+            "GET /api/hassio/backups",
+            ok(json!({"backups": [
+                {"slug": "a1b2c3d4", "name": "Nightly", "date": "2024-01-01T03:00:00+00:00", "type": "full", "size": 512.5},
+                {"slug": "e5f6a7b8", "name": "Before update", "date": "2024-01-02T12:00:00+00:00", "type": "partial",
+                    "protected": true},
+            ]})),
+        ),
+        json("POST /api/hassio/backups/new/partial", ok(json!({"slug": "c9d0e1f2"}))),
+        json("DELETE /api/hassio/backups/a1b2c3d4", json!({"result": "ok", "data": {}})),
+        Route {
+            request: "DELETE /api/hassio/backups/missing",
+            status: 400,
+            content_type: "application/json",
+            body: json!({"result": "error", "message": "Backup does not exist"}).to_string().into_bytes(),
+            delay: Duration::ZERO,
+        },
+    ]);
+    let conn = connect(&server.url);
+    let conn = conn.read().unwrap().clone();
+
+    let backups = conn.get_backups().await.unwrap();
+    assert_eq!(backups.len(), 2);
+    assert_eq!((backups[0].slug.as_str(), backups[0].size, backups[0].protected), ("a1b2c3d4", 512.5, false));
+    assert_eq!((backups[1].kind.as_str(), backups[1].size, backups[1].protected), ("partial", 0.0, true));
+
+    let new_backup = NewBackup {
+        partial: true,
+        name: Some(String::from("Just the config")),
+        homeassistant: Some(true),
+        ..Default::default()
+    };
+    assert_eq!(conn.create_backup(&new_backup).await.unwrap(), "c9d0e1f2");
+    conn.delete_backup("a1b2c3d4").await.unwrap();
+    assert!(matches!(
+        conn.delete_backup("missing").await,
+        Err(Error::Supervisor(message)) if message == "Backup does not exist"
+    ));
+
+    let received = server.received.lock().unwrap();
+    assert!(received.contains(&String::from(
+        r#"POST /api/hassio/backups/new/partial {"name":"Just the config","homeassistant":true}"#
+    )));
+}
+
+#[tokio::test]
+async fn downloads_the_tarball() {
+    let tarball: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+    let server = serve(vec![Route {
+        request: "GET /api/hassio/backups/a1b2c3d4/download",
+        status: 200,
+        content_type: "application/x-tar",
+        body: tarball.clone(),
+        delay: Duration::ZERO,
+    }]);
+    let conn = connect(&server.url);
+    let conn = conn.read().unwrap().clone();
+    let dir = scratch_dir("download");
+
+    let path = dir.join("a1b2c3d4.tar");
+    assert_eq!(conn.download_backup("a1b2c3d4", &path).await.unwrap(), tarball.len() as u64);
+    assert_eq!(std::fs::read(&path).unwrap(), tarball);
+    // Nothing's written for a backup that isn't there.
+    let missing = dir.join("missing.tar");
+    assert!(matches!(conn.download_backup("missing", &missing).await, Err(Error::Status(..))));
+    assert!(!missing.exists());
+}
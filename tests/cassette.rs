@@ -0,0 +1,93 @@
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::json;
+
+use haoscli::error::Error;
+use haoscli::types::{HomeAssistantConnection, State};
+
+use common::{connect, json, scratch_dir, serve, Route, TOKEN};
+
+fn desk(states: &[State]) -> &str {
+    &states.iter().find(|state| state.entity_id == "light.desk").unwrap().state
+}
+
+fn replaying(path: &Path) -> HomeAssistantConnection {
+    // Nothing listens here, anything that gets past the cassette fails.
+    let conn = HomeAssistantConnection::new(String::from("http://127.0.0.1:9"), String::new());
+    conn.write().unwrap().replay_from(path).unwrap();
+    let conn = conn.read().unwrap().clone();
+    conn
+}
+
+#[tokio::test]
+async fn replays_the_fixture_in_order() {
+    let conn = replaying(Path::new("tests/fixtures/states.jsonl"));
+
+    assert_eq!(desk(&conn.get_states().await.unwrap()), "on");
+    let changed = conn
+        .call_service("light", "turn_off", &json!({"entity_id": "light.desk"}))
+        .await
+        .unwrap();
+    assert_eq!(desk(&changed), "off");
+    assert_eq!(desk(&conn.get_states().await.unwrap()), "off");
+    // Used up, so the last answer is repeated like it would be for the UI polling.
+    assert_eq!(desk(&conn.get_states().await.unwrap()), "off");
+}
+
+#[tokio::test]
+async fn replays_errors_and_refuses_what_isnt_on_it() {
+    let conn = replaying(Path::new("tests/fixtures/states.jsonl"));
+
+    assert!(matches!(conn.get_state("light.desk").await, Err(Error::Status(code, _)) if code == 404));
+    assert!(matches!(conn.get_services().await, Err(Error::Cassette(_))));
+    // A different body is a different request.
+    let other = conn.call_service("light", "turn_off", &json!({"entity_id": "light.hall"})).await;
+    assert!(matches!(other, Err(Error::Cassette(_))));
+}
+
+#[tokio::test]
+async fn records_what_it_replays() {
+    let server = serve(vec![
+        json("GET /api/states", json!([{"entity_id": "light.desk", "state": "on",
+            "last_changed": "2024-01-01T10:00:00+00:00", "attributes": {}}])),
+        Route {
+            request: "POST /api/template",
+            status: 200,
+            content_type: "text/plain",
+            body: format!("the token is {}", TOKEN).into_bytes(),
+            delay: Duration::ZERO,
+        },
+        Route {
+            request: "GET /api/hassio/backups/abc123/download",
+            status: 200,
+            content_type: "application/x-tar",
+            body: vec![7; 4096],
+            delay: Duration::ZERO,
+        },
+    ]);
+    let dir = scratch_dir("records");
+    let cassette = dir.join("cassette.jsonl");
+
+    let conn = connect(&server.url);
+    conn.write().unwrap().record_to(cassette.clone()).unwrap();
+    let conn = conn.read().unwrap().clone();
+    let states = conn.get_states().await.unwrap();
+    conn.render_template("{{ 1 }}").await.unwrap();
+    let written = conn.download_backup("abc123", &dir.join("abc123.tar")).await.unwrap();
+    assert_eq!(written, 4096);
+
+    let recorded = std::fs::read_to_string(&cassette).unwrap();
+    assert_eq!(recorded.lines().count(), 3);
+    assert!(!recorded.contains(TOKEN));
+    assert!(recorded.contains("<scrubbed>"));
+    // The tarball went to the file, not the cassette.
+    let download: serde_json::Value = serde_json::from_str(recorded.lines().last().unwrap()).unwrap();
+    assert_eq!(download["body"], serde_json::Value::Null);
+
+    let conn = replaying(&cassette);
+    assert_eq!(desk(&conn.get_states().await.unwrap()), desk(&states));
+    assert_eq!(conn.render_template("{{ 1 }}").await.unwrap(), "the token is <scrubbed>");
+}
@@ -0,0 +1,127 @@
+//! A tiny stand-in for Home Assistant: answers each request with whatever was set up for its method
+//! & path, and remembers what it was sent. Over plain http or https.
+#![allow(dead_code)]
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use openssl::ssl::SslAcceptor;
+
+use haoscli::types::HomeAssistantConnection;
+
+pub const TOKEN: &str = "test-token-1234";
+
+pub struct Route {
+    /// IE: `GET /api/states`
+    pub request: &'static str,
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    /// How long to sit on the request before answering, IE: for an add-on that's slow to restart.
+    pub delay: Duration,
+}
+
+pub fn json(request: &'static str, body: serde_json::Value) -> Route {
+    Route {
+        request,
+        status: 200,
+        content_type: "application/json",
+        body: body.to_string().into_bytes(),
+        delay: Duration::ZERO,
+    }
+}
+
+pub struct MockServer {
+    pub url: String,
+    /// `METHOD /path body` for everything that's come in.
+    pub received: Arc<Mutex<Vec<String>>>,
+}
+
+pub fn serve(routes: Vec<Route>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            answer(stream, &routes, &log);
+        }
+    });
+    MockServer { url, received }
+}
+
+/// The same over https, with whatever certificates `acceptor` was set up with. Connections that
+/// don't get through the handshake are dropped.
+pub fn serve_tls(routes: Vec<Route>, acceptor: SslAcceptor) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://{}", listener.local_addr().unwrap());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let Ok(stream) = acceptor.accept(stream) else { continue };
+            answer(stream, &routes, &log);
+        }
+    });
+    MockServer { url, received }
+}
+
+fn answer<S: Read + Write>(stream: S, routes: &[Route], log: &Mutex<Vec<String>>) {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let request: String = request_line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+    log.lock().unwrap().push(format!("{} {}", request, String::from_utf8_lossy(&body)).trim().to_string());
+    let (status, content_type, body) = match routes.iter().find(|route| route.request == request) {
+        Some(route) => {
+            std::thread::sleep(route.delay);
+            (route.status, route.content_type, route.body.clone())
+        }
+        None => (404, "application/json", br#"{"message": "not found"}"#.to_vec()),
+    };
+    let head = format!(
+        "HTTP/1.1 {} X\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let stream = reader.get_mut();
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&body);
+}
+
+pub fn connect(url: &str) -> Arc<RwLock<HomeAssistantConnection>> {
+    let conn = HomeAssistantConnection::new(url.to_string(), String::from("haoscli-tests"));
+    conn.write().unwrap().set_long_live_token(TOKEN.to_string());
+    conn
+}
+
+/// A fresh directory for a test to write into.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("haoscli-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
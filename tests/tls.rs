@@ -0,0 +1,143 @@
+mod common;
+
+use std::path::PathBuf;
+
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use serde_json::json;
+
+use haoscli::error::{Error, Result};
+use haoscli::types::TlsConfig;
+
+use common::{connect, json, serve_tls, Route};
+
+/// A CA, a certificate it signed for localhost & 127.0.0.1 and a client certificate it signed.
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from("tests/fixtures/tls").join(name)
+}
+
+/// Serves the CA signed certificate, & with `client_certs` won't talk to anyone who doesn't show
+/// one signed by the same CA.
+fn acceptor(client_certs: bool) -> SslAcceptor {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    builder.set_certificate_chain_file(fixture("server.pem")).unwrap();
+    builder.set_private_key_file(fixture("server.key.pem"), SslFiletype::PEM).unwrap();
+    if client_certs {
+        builder.set_ca_file(fixture("ca.pem")).unwrap();
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    builder.build()
+}
+
+fn api_up() -> Vec<Route> {
+    vec![json("GET /api/events", json!([{"event": "state_changed", "listener_count": 1}]))]
+}
+
+/// How many events the server says it has, IE: 1 once we've got through to it.
+async fn api_status(url: &str, tls: TlsConfig) -> Result<usize> {
+    let conn = connect(url);
+    conn.write().unwrap().set_tls_config(tls)?;
+    let conn = conn.read().unwrap().clone();
+    Ok(conn.get_events().await?.len())
+}
+
+#[tokio::test]
+async fn trusts_the_ca_bundle() {
+    let server = serve_tls(api_up(), acceptor(false));
+
+    assert!(matches!(api_status(&server.url, TlsConfig::default()).await, Err(Error::Request(_))));
+    let tls = TlsConfig {
+        ca_bundle: Some(fixture("ca.pem")),
+        ..Default::default()
+    };
+    assert_eq!(api_status(&server.url, tls).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn accepts_invalid_certs_only_for_that_host() {
+    let server = serve_tls(api_up(), acceptor(false));
+
+    let tls = TlsConfig {
+        accept_invalid_certs_for: Some(String::from("127.0.0.1")),
+        ..Default::default()
+    };
+    assert_eq!(api_status(&server.url, tls).await.unwrap(), 1);
+    let tls = TlsConfig {
+        accept_invalid_certs_for: Some(String::from("ha.lan")),
+        ..Default::default()
+    };
+    assert!(matches!(api_status(&server.url, tls).await, Err(Error::Config(_))));
+}
+
+#[tokio::test]
+async fn shows_the_client_certificate() {
+    let server = serve_tls(api_up(), acceptor(true));
+    let trusting = || TlsConfig {
+        ca_bundle: Some(fixture("ca.pem")),
+        ..Default::default()
+    };
+
+    assert!(api_status(&server.url, trusting()).await.is_err());
+    let tls = TlsConfig {
+        client_cert: Some(fixture("client.pem")),
+        client_key: Some(fixture("client.key.pem")),
+        ..trusting()
+    };
+    assert_eq!(api_status(&server.url, tls).await.unwrap(), 1);
+    let tls = TlsConfig {
+        client_cert: Some(fixture("client.pem")),
+        ..trusting()
+    };
+    assert!(matches!(api_status(&server.url, tls).await, Err(Error::Config(_))));
+}
+
+#[cfg(feature = "websocket")]
+mod proxy {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    async fn websocket_through(url: &str, proxy: String) -> Error {
+        let conn = connect(url);
+        let tls = TlsConfig {
+            proxy: Some(proxy),
+            ..Default::default()
+        };
+        conn.write().unwrap().set_tls_config(tls).unwrap();
+        let conn = conn.read().unwrap().clone();
+        match conn.connect_websocket().await {
+            Ok(_) => panic!("the websocket shouldn't have opened"),
+            Err(e) => e,
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_https_proxies() {
+        let e = websocket_through("http://127.0.0.1:9", String::from("https://proxy.lan:3128")).await;
+        assert!(matches!(e, Error::Config(_)));
+    }
+
+    #[tokio::test]
+    async fn logs_in_to_the_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("http://me:s%40cret@{}", listener.local_addr().unwrap());
+        let asked = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").unwrap();
+            String::from_utf8(head).unwrap()
+        });
+
+        let e = websocket_through("http://ha.lan:8123", proxy).await;
+        assert!(matches!(&e, Error::WebSocket(message) if message.contains("407")));
+        let head = asked.join().unwrap();
+        assert!(head.starts_with("CONNECT ha.lan:8123 HTTP/1.1\r\n"));
+        // me:s@cret
+        assert!(head.contains("Proxy-Authorization: Basic bWU6c0BjcmV0\r\n"));
+    }
+}